@@ -0,0 +1,32 @@
+use crate::formats::pws::data::PwsFile;
+use crate::raster::components::BoundingBox;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Bounding box of the lit pixels of a single layer, None if the layer is empty.
+pub fn layer_bounds(image: &GrayImage) -> Option<BoundingBox> {
+    image
+        .enumerate_pixels()
+        .filter(|(_, _, pixel)| pixel.0[0] > 0)
+        .fold(None, |bounds: Option<BoundingBox>, (x, y, _)| {
+            Some(match bounds {
+                None => BoundingBox::new(x, y),
+                Some(bounds) => bounds.union(&BoundingBox::new(x, y)),
+            })
+        })
+}
+
+/// Bounding box of the lit pixels over all layers, None if every layer is empty.
+pub fn occupied_bounds<F>(file: &PwsFile, on_layer: F) -> Option<BoundingBox>
+where
+    F: Fn(usize) + Sync,
+{
+    (0..file.layers.len())
+        .into_par_iter()
+        .filter_map(|index| {
+            let bounds = layer_bounds(&file.decode_layer(index));
+            on_layer(index);
+            bounds
+        })
+        .reduce_with(|a, b| a.union(&b))
+}
@@ -0,0 +1,253 @@
+use crate::formats::pws::data::PwsFile;
+use crate::raster::components::{label_4_connected, BoundingBox, Labels};
+use crate::raster::Bitmap;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Empty space is labelled layer by layer, and components in consecutive layers that share a pixel
+ * are joined with a union-find over all layers. Lit pixels connect diagonally, so empty space only
+ * connects through pixel edges, or a wall of diagonal steps would leak. Anything that reaches the
+ * edge of the image is open air. Whatever remains is enclosed by the part (and the build plate),
+ * at least while it is being printed.
+ *
+ * A pocket that is enclosed in its lower layers but opens to the air further up is still a
+ * suction cup while those lower layers print. It is recorded as it was just before joining the air.
+ */
+
+/// Number of layers decoded and labelled in parallel before being linked together.
+const CHUNK_SIZE: usize = 16;
+
+#[derive(Clone, Debug)]
+pub struct Cavity {
+    pub first_layer: usize, // Closest to the build plate
+    pub last_layer: usize,
+    pub voxels: usize,
+    pub volume: f32,         // in mm^3
+    pub bounds: BoundingBox, // in pixels, over all layers
+    pub bottom: (f32, f32),  // centroid of the cross-section in first_layer, in mm
+    pub seed: (u32, u32),    // A pixel of the cavity in first_layer
+    // Reaches the last layer or opens to the air further up, so only a suction cup while printing
+    pub open_top: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    parent: usize,
+    voxels: usize,
+    volume: f32, // in pixel * mm
+    first_layer: usize,
+    last_layer: usize,
+    bounds: BoundingBox,
+    bottom_sum: (u64, u64, usize),
+    seed: (u32, u32),
+    open: bool,
+}
+
+fn find(nodes: &mut [Node], node: usize) -> usize {
+    let mut root = node;
+    while nodes[root].parent != root {
+        root = nodes[root].parent;
+    }
+    let mut current = node;
+    while nodes[current].parent != root {
+        let next = nodes[current].parent;
+        nodes[current].parent = root;
+        current = next;
+    }
+    root
+}
+
+fn union(nodes: &mut [Node], a: usize, b: usize) {
+    let a = find(nodes, a);
+    let b = find(nodes, b);
+    if a == b {
+        return;
+    }
+    let (root, child_index) = if a < b { (a, b) } else { (b, a) };
+    let child = nodes[child_index];
+    let node = &mut nodes[root];
+    node.voxels += child.voxels;
+    node.volume += child.volume;
+    node.open |= child.open;
+    node.bounds = node.bounds.union(&child.bounds);
+    if child.first_layer < node.first_layer {
+        node.first_layer = child.first_layer;
+        node.bottom_sum = child.bottom_sum;
+        node.seed = child.seed;
+    } else if child.first_layer == node.first_layer {
+        node.bottom_sum.0 += child.bottom_sum.0;
+        node.bottom_sum.1 += child.bottom_sum.1;
+        node.bottom_sum.2 += child.bottom_sum.2;
+    }
+    node.last_layer = std::cmp::max(node.last_layer, child.last_layer);
+    nodes[child_index].parent = root;
+}
+
+/// Links two components in consecutive layers, where `layer` is the upper one. A pocket that only
+/// now joins the open air is added to `opened` first.
+fn link(nodes: &mut [Node], a: usize, b: usize, layer: usize, opened: &mut Vec<Node>) {
+    let a = find(nodes, a);
+    let b = find(nodes, b);
+    if a == b {
+        return;
+    }
+    if nodes[a].open != nodes[b].open {
+        let closed = if nodes[a].open { b } else { a };
+        if nodes[closed].first_layer < layer {
+            let mut pocket = nodes[closed];
+            pocket.last_layer = layer - 1;
+            pocket.open = true; // Opens in `layer`
+            opened.push(pocket);
+        }
+    }
+    union(nodes, a, b);
+}
+
+fn touches_border(labels: &Labels, component: usize) -> bool {
+    let bounds = &labels.components[component].bounds;
+    bounds.min_x == 0
+        || bounds.min_y == 0
+        || bounds.max_x + 1 == labels.width
+        || bounds.max_y + 1 == labels.height
+}
+
+/// Finds enclosed empty volumes. Calls `on_layer` once for every layer processed.
+pub fn find_cavities<F>(file: &PwsFile, on_layer: F) -> Vec<Cavity>
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let mut nodes: Vec<Node> = Vec::new();
+    let mut opened: Vec<Node> = Vec::new();
+    // Labels of the previous layer, and the node index of its first component.
+    let mut previous: Option<(Labels, usize)> = None;
+    let num_layers = file.layers.len();
+    for chunk_start in (0..num_layers).step_by(CHUNK_SIZE) {
+        let chunk_end = std::cmp::min(num_layers, chunk_start + CHUNK_SIZE);
+        let chunk_labels: Vec<Labels> = (chunk_start..chunk_end)
+            .into_par_iter()
+            .map(|index| {
+                let labels =
+                    label_4_connected(&Bitmap::from_image(&file.decode_layer(index)), false);
+                on_layer(index);
+                labels
+            })
+            .collect();
+        for (offset, labels) in chunk_labels.into_iter().enumerate() {
+            let index = chunk_start + offset;
+            let thickness = file.layer_thickness(index);
+            let first_node = nodes.len();
+            let mut seeds = vec![(0, 0); labels.components.len()];
+            for (pixel, label) in labels.labels.iter().enumerate().rev() {
+                if *label != 0 {
+                    let pixel = pixel as u32;
+                    seeds[*label as usize - 1] = (pixel % labels.width, pixel / labels.width);
+                }
+            }
+            for (component_index, component) in labels.components.iter().enumerate() {
+                nodes.push(Node {
+                    parent: first_node + component_index,
+                    voxels: component.area,
+                    volume: component.area as f32 * thickness,
+                    first_layer: index,
+                    last_layer: index,
+                    bounds: component.bounds,
+                    bottom_sum: (component.sum_x, component.sum_y, component.area),
+                    seed: seeds[component_index],
+                    open: touches_border(&labels, component_index),
+                });
+            }
+            if let Some((previous_labels, previous_first_node)) = previous {
+                for (current, previous) in labels.labels.iter().zip(previous_labels.labels.iter()) {
+                    if *current != 0 && *previous != 0 {
+                        link(
+                            &mut nodes,
+                            first_node + *current as usize - 1,
+                            previous_first_node + *previous as usize - 1,
+                            index,
+                            &mut opened,
+                        );
+                    }
+                }
+            }
+            previous = Some((labels, first_node));
+        }
+    }
+
+    let mut enclosed = Vec::new();
+    for index in 0..nodes.len() {
+        if find(&mut nodes, index) == index && !nodes[index].open {
+            enclosed.push(nodes[index]);
+        }
+    }
+    enclosed
+        .iter()
+        .chain(opened.iter())
+        .map(|node| {
+            let (sum_x, sum_y, count) = node.bottom_sum;
+            Cavity {
+                first_layer: node.first_layer,
+                last_layer: node.last_layer,
+                voxels: node.voxels,
+                volume: node.volume * pixel_size * pixel_size,
+                bounds: node.bounds,
+                bottom: (
+                    sum_x as f32 / count as f32 * pixel_size,
+                    sum_y as f32 / count as f32 * pixel_size,
+                ),
+                seed: node.seed,
+                open_top: node.open || node.last_layer + 1 == num_layers,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_find_cavities() {
+    use crate::generate::test_job;
+    use image::{GrayImage, Luma};
+
+    // A 5x5 cup on a 7x7 plate: a solid floor, then walls around a 3x3 hole
+    let floor = GrayImage::from_fn(7, 7, |x, y| {
+        Luma([if (1..6).contains(&x) && (1..6).contains(&y) {
+            255
+        } else {
+            0
+        }])
+    });
+    let mut walls = floor.clone();
+    for y in 2..5 {
+        for x in 2..5 {
+            walls.put_pixel(x, y, Luma([0]));
+        }
+    }
+    let mut gap = walls.clone();
+    gap.put_pixel(3, 1, Luma([0]));
+    // Without its corner the wall only closes diagonally
+    let mut diagonal = walls.clone();
+    diagonal.put_pixel(1, 1, Luma([0]));
+    let job = |images: &[&GrayImage]| {
+        let images: Vec<GrayImage> = images.iter().map(|image| (*image).clone()).collect();
+        test_job(7, 7, &images)
+    };
+
+    // Open at the last layer only
+    let cavities = find_cavities(&job(&[&floor, &walls, &walls, &walls]), |_| {});
+    assert_eq!(cavities.len(), 1);
+    assert_eq!((cavities[0].first_layer, cavities[0].last_layer), (1, 3));
+    assert_eq!(cavities[0].voxels, 27);
+    assert!(cavities[0].open_top);
+    // Closed by a lid, so it traps resin
+    let cavities = find_cavities(&job(&[&floor, &walls, &walls, &floor]), |_| {});
+    assert_eq!(cavities.len(), 1);
+    assert!(!cavities[0].open_top);
+    let cavities = find_cavities(&job(&[&floor, &diagonal, &diagonal, &floor]), |_| {});
+    assert_eq!(cavities.len(), 1);
+    assert_eq!(cavities[0].voxels, 18);
+    assert!(!cavities[0].open_top);
+    // Opens to the side partway up
+    let cavities = find_cavities(&job(&[&floor, &walls, &walls, &gap, &gap]), |_| {});
+    assert_eq!(cavities.len(), 1);
+    assert_eq!((cavities[0].first_layer, cavities[0].last_layer), (1, 2));
+    assert!(cavities[0].open_top);
+}
@@ -0,0 +1,169 @@
+use crate::formats::pws::data::PwsFile;
+use crate::raster::components::{label, BoundingBox, Labels};
+use crate::raster::distance::distance_to;
+use crate::raster::morphology::{dilate, erode};
+use crate::raster::Bitmap;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Thin walls are found with a morphological opening: eroding and then dilating by half the minimum
+ * width removes exactly the parts of a layer that a disc of that width does not fit in. The
+ * opening also rounds off convex corners, but those leftovers never reach further than about 0.4
+ * times the radius from the opened shape, so only regions reaching at least half the radius away
+ * are reported.
+ *
+ * Pins are small connected regions that the next layer continues from. The peel force of every
+ * later layer is carried through them, so they tear off where a thin wall of the same width
+ * would survive. Pins are not reported as thin walls as well.
+ */
+
+#[derive(Clone, Copy, Debug)]
+pub struct FeatureLimits {
+    pub min_width: f32,    // Narrowest wall that cures reliably, in mm
+    pub min_pin_area: f32, // Smallest cross-section that survives lifting, in mm^2
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FeatureKind {
+    ThinWall,
+    Pin,
+}
+
+impl FeatureKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FeatureKind::ThinWall => "thin-wall",
+            FeatureKind::Pin => "pin",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ThinFeature {
+    pub layer: usize,
+    pub kind: FeatureKind,
+    pub centroid: (f32, f32), // in mm, from the top-left corner of the plate
+    pub area: f32,            // in mm^2
+    pub bounds: BoundingBox,  // in pixels
+}
+
+/// Thin walls and pins of a single layer. `next` is the layer printed after it, if any.
+pub fn find_layer_features(
+    layer: usize,
+    current: &Bitmap,
+    next: Option<&Bitmap>,
+    limits: &FeatureLimits,
+    pixel_size: f32,
+) -> Vec<ThinFeature> {
+    let to_feature = |kind: FeatureKind, labels: &Labels, component: usize| {
+        let component = &labels.components[component];
+        let (x, y) = component.centroid();
+        ThinFeature {
+            layer,
+            kind,
+            centroid: (x * pixel_size, y * pixel_size),
+            area: component.area as f32 * pixel_size * pixel_size,
+            bounds: component.bounds,
+        }
+    };
+
+    let regions = label(current, true);
+    let min_pin_pixels = limits.min_pin_area / (pixel_size * pixel_size);
+    let mut is_pin = vec![false; regions.components.len()];
+    if let Some(next) = next {
+        for (index, region) in regions.labels.iter().enumerate() {
+            if *region != 0 && next.data[index] {
+                let region = *region as usize - 1;
+                is_pin[region] = (regions.components[region].area as f32) < min_pin_pixels;
+            }
+        }
+    }
+    let mut features: Vec<ThinFeature> = is_pin
+        .iter()
+        .enumerate()
+        .filter(|(_, pin)| **pin)
+        .map(|(region, _)| to_feature(FeatureKind::Pin, &regions, region))
+        .collect();
+
+    let radius = limits.min_width / 2.0 / pixel_size;
+    let opened = dilate(&erode(&current.to_image(), radius), radius);
+    let mut thin = Bitmap::new(current.width, current.height);
+    for (index, (lit, opened)) in current.data.iter().zip(opened.pixels()).enumerate() {
+        let pin = regions.labels[index] != 0 && is_pin[regions.labels[index] as usize - 1];
+        thin.data[index] = *lit && opened.0[0] < 128 && !pin;
+    }
+    let reach = distance_to(&Bitmap::from_image(&opened), true);
+    let thin_regions = label(&thin, true);
+    let mut furthest = vec![0.0f32; thin_regions.components.len()];
+    for (index, region) in thin_regions.labels.iter().enumerate() {
+        if *region != 0 {
+            let region = *region as usize - 1;
+            furthest[region] = furthest[region].max(reach[index]);
+        }
+    }
+    features.extend(
+        furthest
+            .iter()
+            .enumerate()
+            .filter(|(_, furthest)| **furthest >= radius / 2.0)
+            .map(|(region, _)| to_feature(FeatureKind::ThinWall, &thin_regions, region)),
+    );
+    features
+}
+
+/// Runs the thin feature check over every layer of the file. Calls `on_layer` with the index of
+/// every layer checked.
+pub fn find_thin_features<F>(
+    file: &PwsFile,
+    limits: &FeatureLimits,
+    on_layer: F,
+) -> Vec<ThinFeature>
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let num_layers = file.layers.len();
+    (0..num_layers)
+        .into_par_iter()
+        .flat_map(|index| {
+            let current = Bitmap::from_image(&file.decode_layer(index));
+            let next = if index + 1 < num_layers {
+                Some(Bitmap::from_image(&file.decode_layer(index + 1)))
+            } else {
+                None
+            };
+            let features = find_layer_features(index, &current, next.as_ref(), limits, pixel_size);
+            on_layer(index);
+            features
+        })
+        .collect()
+}
+
+#[test]
+fn test_find_layer_features() {
+    // A 6x6 block with a one pixel fin, and a single pixel that the next layer continues from
+    let mut current = Bitmap::new(16, 8);
+    for y in 1..7 {
+        for x in 0..6 {
+            current.set(x, y, true);
+        }
+    }
+    for x in 6..12 {
+        current.set(x, 3, true);
+    }
+    current.set(14, 6, true);
+    let mut next = Bitmap::new(16, 8);
+    next.set(14, 6, true);
+    let limits = FeatureLimits {
+        min_width: 3.0,
+        min_pin_area: 2.0,
+    };
+    let features = find_layer_features(0, &current, Some(&next), &limits, 1.0);
+    assert_eq!(features.len(), 2);
+    assert_eq!(features[0].kind, FeatureKind::Pin);
+    assert_eq!(features[0].centroid, (14.0, 6.0));
+    assert_eq!(features[1].kind, FeatureKind::ThinWall);
+    assert_eq!(features[1].bounds.max_x, 11);
+    assert_eq!(features[1].bounds.min_y, 3);
+    assert_eq!(features[1].bounds.max_y, 3);
+}
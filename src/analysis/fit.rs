@@ -0,0 +1,173 @@
+use crate::analysis::bounds::layer_bounds;
+use crate::formats::pws::data::PwsFile;
+use crate::printer::Printer;
+use crate::raster::components::BoundingBox;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Jobs are placed on another printer the way `transform resample` does it, with the centres of
+ * both screens aligned. A part fits when its occupied area stays within the other screen around
+ * that centre, which can fail even when its size would fit.
+ */
+
+const TOLERANCE: f32 = 1e-3;
+
+#[derive(Clone, Debug)]
+pub struct Extent {
+    pub bounds: Option<BoundingBox>, // Occupied area over all layers, in pixels
+    pub edge_layers: Vec<usize>,     // Layers with lit pixels on the edge of the image
+}
+
+/// Measures the occupied area of the job, and which layers reach the edge of the image.
+pub fn measure_extent<F>(file: &PwsFile, on_layer: F) -> Extent
+where
+    F: Fn(usize) + Sync,
+{
+    let (width, height) = (file.header.width, file.header.height);
+    let layer_bounds: Vec<Option<BoundingBox>> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let bounds = layer_bounds(&file.decode_layer(index));
+            on_layer(index);
+            bounds
+        })
+        .collect();
+    let bounds = layer_bounds
+        .iter()
+        .flatten()
+        .fold(None, |total: Option<BoundingBox>, bounds| {
+            Some(match total {
+                None => *bounds,
+                Some(total) => total.union(bounds),
+            })
+        });
+    let edge_layers = layer_bounds
+        .iter()
+        .enumerate()
+        .filter(|(_, bounds)| {
+            bounds.is_some_and(|bounds| {
+                bounds.min_x == 0
+                    || bounds.min_y == 0
+                    || bounds.max_x + 1 == width
+                    || bounds.max_y + 1 == height
+            })
+        })
+        .map(|(index, _)| index)
+        .collect();
+    Extent {
+        bounds,
+        edge_layers,
+    }
+}
+
+/// Problems printing the job on `printer`, empty if it fits.
+pub fn check_fit(file: &PwsFile, extent: &Extent, printer: &Printer) -> Vec<String> {
+    let header = &file.header;
+    let mut problems = Vec::new();
+    if header.width != printer.width || header.height != printer.height {
+        problems.push(format!(
+            "Job is {} x {} pixels instead of {} x {}, resample it first",
+            header.width, header.height, printer.width, printer.height
+        ));
+    }
+    if (header.pixel_size - printer.pixel_size).abs() > TOLERANCE {
+        problems.push(format!(
+            "Job has pixel size {} um instead of {} um, resample it first",
+            header.pixel_size, printer.pixel_size
+        ));
+    }
+
+    if let Some(bounds) = extent.bounds {
+        // Edges of the occupied area in mm from the centre of the screen
+        let pixel_size = header.pixel_size_mm();
+        let left = (bounds.min_x as f32 - header.width as f32 / 2.0) * pixel_size;
+        let right = ((bounds.max_x + 1) as f32 - header.width as f32 / 2.0) * pixel_size;
+        let top = (bounds.min_y as f32 - header.height as f32 / 2.0) * pixel_size;
+        let bottom = ((bounds.max_y + 1) as f32 - header.height as f32 / 2.0) * pixel_size;
+        let plate_width = printer.width as f32 * printer.pixel_size_mm();
+        let plate_height = printer.height as f32 * printer.pixel_size_mm();
+        let (part_width, part_height) = (right - left, bottom - top);
+        if part_width > plate_width + TOLERANCE || part_height > plate_height + TOLERANCE {
+            let rotated =
+                part_height <= plate_width + TOLERANCE && part_width <= plate_height + TOLERANCE;
+            problems.push(format!(
+                "Occupied area of {:.2} x {:.2} mm does not fit the {:.2} x {:.2} mm screen{}",
+                part_width,
+                part_height,
+                plate_width,
+                plate_height,
+                if rotated {
+                    ", rotate it by 90 degrees"
+                } else {
+                    ""
+                }
+            ));
+        } else if -left > plate_width / 2.0 + TOLERANCE
+            || right > plate_width / 2.0 + TOLERANCE
+            || -top > plate_height / 2.0 + TOLERANCE
+            || bottom > plate_height / 2.0 + TOLERANCE
+        {
+            problems.push(format!(
+                "Occupied area of {:.2} x {:.2} mm is too far off centre for the {:.2} x {:.2} mm \
+                 screen, move it first",
+                part_width, part_height, plate_width, plate_height
+            ));
+        }
+    }
+
+    if let Some(first) = extent.edge_layers.first() {
+        problems.push(format!(
+            "{} layers have lit pixels on the edge of the image, starting at layer {}, the job \
+             may have been cropped when it was sliced",
+            extent.edge_layers.len(),
+            first
+        ));
+    }
+
+    let mut top = 0.0;
+    for index in 0..file.layers.len() {
+        top += file.layer_thickness(index);
+        if top > printer.max_z + TOLERANCE {
+            problems.push(format!(
+                "Layers {} to {} are above the {} mm Z travel of the printer",
+                index,
+                file.layers.len() - 1,
+                printer.max_z
+            ));
+            break;
+        }
+    }
+    problems
+}
+
+#[test]
+fn test_check_fit() {
+    use crate::generate::{new_job, new_layer, PrintSettings};
+    use image::GrayImage;
+
+    let photon_s = Printer::by_name("photon-s").unwrap();
+    let mut file = new_job(photon_s, &PrintSettings::default(), 1);
+    let image = GrayImage::new(file.header.width, file.header.height);
+    file.layers = vec![
+        new_layer(&file, &image, 8.0, 100.0),
+        new_layer(&file, &image, 8.0, 100.0),
+    ];
+    // Full width on the top half of the screen
+    let extent = Extent {
+        bounds: Some(BoundingBox {
+            min_x: 0,
+            min_y: 0,
+            max_x: 1439,
+            max_y: 1279,
+        }),
+        edge_layers: vec![1],
+    };
+    let problems = check_fit(&file, &extent, photon_s);
+    assert_eq!(problems.len(), 2); // Edge pixels, and too high
+    assert!(problems[1].starts_with("Layers 1 to 1"));
+    // Small enough for the Mono X, but the top edge ends up just off its shorter screen
+    let problems = check_fit(&file, &extent, Printer::by_name("photon-mono-x").unwrap());
+    assert_eq!(problems.len(), 4);
+    assert!(problems[1].contains("pixel size"));
+    assert!(problems[2].contains("off centre"));
+}
@@ -0,0 +1,98 @@
+use crate::formats::pws::data::PwsFile;
+use crate::raster::components::{label, Labels};
+use crate::raster::Bitmap;
+use image::{GrayImage, Rgb, RgbImage};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// A connected region of a layer that has no lit pixels directly beneath it.
+#[derive(Clone, Debug)]
+pub struct Island {
+    pub layer: usize,
+    pub centroid: (f32, f32), // in mm, from the top-left corner of the plate
+    pub area: f32,            // in mm^2
+    pub pixels: usize,
+}
+
+pub struct LayerIslands {
+    pub labels: Labels,
+    pub islands: Vec<usize>, // indices into labels.components
+}
+
+/// Finds components of `current` that do not overlap `previous` at all.
+pub fn find_layer_islands(previous: &Bitmap, current: &Bitmap) -> LayerIslands {
+    let labels = label(current, true);
+    let mut supported = vec![false; labels.components.len()];
+    for (index, label) in labels.labels.iter().enumerate() {
+        if *label != 0 && previous.data[index] {
+            supported[*label as usize - 1] = true;
+        }
+    }
+    let islands = supported
+        .iter()
+        .enumerate()
+        .filter(|(_, supported)| !**supported)
+        .map(|(index, _)| index)
+        .collect();
+    LayerIslands { labels, islands }
+}
+
+/// Runs island detection over every layer of the file. The first layer sits on the build plate,
+/// and is never reported. Calls `on_layer` with the decoded layer and its islands (which may be
+/// empty), e.g. for progress reporting or writing marked-up images.
+pub fn find_islands<F>(file: &PwsFile, on_layer: F) -> Vec<Island>
+where
+    F: Fn(usize, &GrayImage, &LayerIslands) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    (1..file.layers.len())
+        .into_par_iter()
+        .flat_map(|index| {
+            let previous = Bitmap::from_image(&file.decode_layer(index - 1));
+            let image = file.decode_layer(index);
+            let layer_islands = find_layer_islands(&previous, &Bitmap::from_image(&image));
+            on_layer(index, &image, &layer_islands);
+            layer_islands
+                .islands
+                .iter()
+                .map(|component| {
+                    let component = &layer_islands.labels.components[*component];
+                    let (x, y) = component.centroid();
+                    Island {
+                        layer: index,
+                        centroid: (x * pixel_size, y * pixel_size),
+                        area: component.area as f32 * pixel_size * pixel_size,
+                        pixels: component.area,
+                    }
+                })
+                .collect::<Vec<Island>>()
+        })
+        .collect()
+}
+
+/// Greyscale layer with islands highlighted in red.
+pub fn mark_islands(image: &GrayImage, layer_islands: &LayerIslands) -> RgbImage {
+    let mut is_island = vec![false; layer_islands.labels.components.len()];
+    for island in layer_islands.islands.iter() {
+        is_island[*island] = true;
+    }
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let value = image.get_pixel(x, y).0[0];
+        match layer_islands.labels.get(x, y) {
+            Some(component) if is_island[component] => Rgb([255, 0, 0]),
+            _ => Rgb([value, value, value]),
+        }
+    })
+}
+
+#[test]
+fn test_find_layer_islands() {
+    let mut previous = Bitmap::new(8, 1);
+    previous.set(0, 0, true);
+    let mut current = Bitmap::new(8, 1);
+    current.set(0, 0, true);
+    current.set(1, 0, true);
+    current.set(5, 0, true);
+    let result = find_layer_islands(&previous, &current);
+    assert_eq!(result.labels.components.len(), 2);
+    assert_eq!(result.islands, vec![1]);
+}
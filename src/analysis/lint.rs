@@ -0,0 +1,302 @@
+use crate::formats::pws::data::PwsFile;
+
+/*
+ * Sanity checks on the print parameters and layer encoding, without decoding any layers. Errors
+ * are settings a printer can not print at all, warnings are settings that are valid but most
+ * likely not what was intended.
+ */
+
+const TOLERANCE: f32 = 1e-4;
+const MAX_EXPOSURE_TIME: f32 = 120.0; // in sec, longer is most likely a typo
+const MAX_BOTTOM_EXPOSURE_TIME: f32 = 300.0; // in sec
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Issue {
+    pub severity: Severity,
+    pub message: String,
+    pub layers: Vec<usize>, // Layers the issue applies to, empty for header settings
+}
+
+impl Issue {
+    fn header(severity: Severity, message: String) -> Issue {
+        Issue {
+            severity,
+            message,
+            layers: Vec::new(),
+        }
+    }
+}
+
+/// Severity of an exposure time, None if it is reasonable.
+fn exposure_severity(time: f32, max_time: f32) -> Option<Severity> {
+    if time <= 0.0 {
+        Some(Severity::Error)
+    } else if time > max_time {
+        Some(Severity::Warning)
+    } else {
+        None
+    }
+}
+
+/// Adds an issue for the layers that match `check`, if any.
+fn check_layers<F>(
+    file: &PwsFile,
+    issues: &mut Vec<Issue>,
+    severity: Severity,
+    message: &str,
+    check: F,
+) where
+    F: Fn(usize) -> bool,
+{
+    let layers: Vec<usize> = (0..file.layers.len())
+        .filter(|index| check(*index))
+        .collect();
+    if !layers.is_empty() {
+        issues.push(Issue {
+            severity,
+            message: message.to_string(),
+            layers,
+        });
+    }
+}
+
+/// All problems found in the header and layers of the job.
+pub fn lint_job(file: &PwsFile) -> Vec<Issue> {
+    let header = &file.header;
+    let num_layers = file.layers.len();
+    let mut issues = Vec::new();
+
+    if ![1, 2, 4, 8].contains(&header.bits_per_pixel) {
+        issues.push(Issue::header(
+            Severity::Error,
+            format!(
+                "bits_per_pixel is {} instead of 1, 2, 4 or 8",
+                header.bits_per_pixel
+            ),
+        ));
+    }
+    if header.width == 0 || header.height == 0 {
+        issues.push(Issue::header(
+            Severity::Error,
+            format!("Resolution is {} x {} pixels", header.width, header.height),
+        ));
+    }
+    if header.layer_height <= 0.0 {
+        issues.push(Issue::header(
+            Severity::Error,
+            format!("Layer height is {} mm", header.layer_height),
+        ));
+    }
+    if header.num_bottom_layers < 0.0 || header.num_bottom_layers.fract() != 0.0 {
+        issues.push(Issue::header(
+            Severity::Warning,
+            format!(
+                "num_bottom_layers is {} instead of a whole number",
+                header.num_bottom_layers
+            ),
+        ));
+    }
+    if header.num_bottom_layers > num_layers as f32 {
+        issues.push(Issue::header(
+            Severity::Warning,
+            format!(
+                "num_bottom_layers is {} but the job only has {} layers",
+                header.num_bottom_layers, num_layers
+            ),
+        ));
+    }
+    let exposures = [
+        ("Exposure time", header.exposure_time, MAX_EXPOSURE_TIME),
+        (
+            "Bottom exposure time",
+            header.bottom_exposure_time,
+            MAX_BOTTOM_EXPOSURE_TIME,
+        ),
+    ];
+    for (name, time, max_time) in exposures.iter() {
+        if let Some(severity) = exposure_severity(*time, *max_time) {
+            issues.push(Issue::header(
+                severity,
+                format!(
+                    "{} of {} sec is not between 0 and {} sec",
+                    name, time, max_time
+                ),
+            ));
+        }
+    }
+    if header.lift_speed <= 0.0 {
+        issues.push(Issue::header(
+            Severity::Error,
+            format!("Lift speed is {} mm/sec", header.lift_speed),
+        ));
+    }
+    if header.drop_speed <= 0.0 {
+        issues.push(Issue::header(
+            Severity::Error,
+            format!("Drop speed is {} mm/sec", header.drop_speed),
+        ));
+    }
+
+    let image_size = header.width as usize * header.height as usize;
+    if image_size > 0 {
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Error,
+            "Layer data is empty",
+            |index| file.layers[index].data.decoded_len() == 0,
+        );
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Error,
+            "Layer data does not decode to a whole number of images",
+            |index| {
+                !file.layers[index]
+                    .data
+                    .decoded_len()
+                    .is_multiple_of(image_size)
+            },
+        );
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Warning,
+            &format!(
+                "Layer data does not have {} bits per pixel",
+                header.bits_per_pixel
+            ),
+            |index| {
+                let decoded_len = file.layers[index].data.decoded_len();
+                decoded_len > 0
+                    && decoded_len.is_multiple_of(image_size)
+                    && decoded_len / image_size != header.bits_per_pixel as usize
+            },
+        );
+    }
+
+    let leading_blank = file
+        .layers
+        .iter()
+        .take_while(|layer| layer.data.is_blank())
+        .count();
+    if leading_blank > 0 {
+        issues.push(Issue {
+            severity: Severity::Warning,
+            message: if leading_blank == num_layers {
+                "All layers are empty".to_string()
+            } else {
+                "Leading layers are empty, the part does not start on the build plate".to_string()
+            },
+            layers: (0..leading_blank).collect(),
+        });
+    }
+
+    if header.use_individual_parameters {
+        let bottom_layers = header.num_bottom_layers.max(0.0).ceil() as usize;
+        let exposure_issue = |index: usize| {
+            let max_time = if index < bottom_layers {
+                MAX_BOTTOM_EXPOSURE_TIME
+            } else {
+                MAX_EXPOSURE_TIME
+            };
+            exposure_severity(file.layers[index].exposure_time, max_time)
+        };
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Error,
+            "Layer exposure time is zero or negative",
+            |index| exposure_issue(index) == Some(Severity::Error),
+        );
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Warning,
+            &format!(
+                "Layer exposure time is over {} sec ({} sec for bottom layers)",
+                MAX_EXPOSURE_TIME, MAX_BOTTOM_EXPOSURE_TIME
+            ),
+            |index| exposure_issue(index) == Some(Severity::Warning),
+        );
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Error,
+            "Layer lift speed is zero or negative",
+            |index| file.layers[index].lift_speed <= 0.0,
+        );
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Error,
+            "Layer height is zero or negative",
+            |index| file.layers[index].layer_height <= 0.0,
+        );
+    } else if let Some(first) = file.layers.first() {
+        check_layers(
+            file,
+            &mut issues,
+            Severity::Warning,
+            "Layer heights differ, but are ignored as use_individual_parameters is off",
+            |index| (file.layers[index].layer_height - first.layer_height).abs() > TOLERANCE,
+        );
+    }
+    issues
+}
+
+#[test]
+fn test_lint_job() {
+    use crate::formats::pws::data::CompressedBitstream;
+    use crate::generate::{new_job, new_layer, PrintSettings};
+    use crate::printer::Printer;
+    use image::{GrayImage, Luma};
+
+    let printer = Printer::by_name("photon-s").unwrap();
+    let mut file = new_job(printer, &PrintSettings::default(), 1);
+    let blank = GrayImage::new(file.header.width, file.header.height);
+    let lit = GrayImage::from_pixel(file.header.width, file.header.height, Luma([255]));
+    file.layers = vec![
+        new_layer(&file, &blank, 60.0, 0.05),
+        new_layer(&file, &lit, 60.0, 0.05),
+        new_layer(&file, &lit, 0.0, 0.05),
+    ];
+    file.header.num_bottom_layers = 1.5;
+    assert_eq!(lint_job(&file).len(), 3);
+
+    file.header.use_individual_parameters = false;
+    file.header.num_bottom_layers = 1.0;
+    file.layers[2].layer_height = 0.1;
+    let issues = lint_job(&file);
+    assert_eq!(issues.len(), 2);
+    assert_eq!(issues[0].layers, vec![0]);
+    assert_eq!(issues[1].layers, vec![2]);
+
+    // Layers without any data can not be printed, whatever their depth
+    file.layers[2].data = CompressedBitstream::default();
+    let issues = lint_job(&file);
+    let empty = issues
+        .iter()
+        .find(|issue| issue.message == "Layer data is empty")
+        .unwrap();
+    assert_eq!(empty.severity, Severity::Error);
+    assert_eq!(empty.layers, vec![2]);
+    assert!(!issues
+        .iter()
+        .any(|issue| issue.message.contains("bits per pixel")));
+}
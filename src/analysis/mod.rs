@@ -0,0 +1,8 @@
+pub mod bounds;
+pub mod cavities;
+pub mod features;
+pub mod fit;
+pub mod islands;
+pub mod lint;
+pub mod profile;
+pub mod volume;
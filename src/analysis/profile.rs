@@ -0,0 +1,104 @@
+use crate::analysis::bounds::layer_bounds;
+use crate::analysis::volume::image_area;
+use crate::formats::pws::data::PwsFile;
+use crate::raster::components::{label, BoundingBox};
+use crate::raster::Bitmap;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Cross-section profile of a job, layer by layer. The peel force on a layer grows with its lit
+ * area, and the suction holding it to the vat with the area of its largest solid region, so
+ * sudden jumps in either show where a print is most likely to tear off.
+ *
+ * The perimeter counts the pixel edges between lit and unlit pixels, which overestimates
+ * diagonal edges by up to sqrt(2).
+ */
+
+#[derive(Clone, Debug)]
+pub struct LayerProfile {
+    pub layer: usize,
+    pub z: f32,                      // Top of the layer, in mm
+    pub area: f32,                   // Lit area, in mm^2
+    pub perimeter: f32,              // Length of all outlines, in mm
+    pub largest_component: f32,      // Area of the largest connected region, in mm^2
+    pub bounds: Option<BoundingBox>, // In pixels, None for an empty layer
+    pub area_change: f32,            // Area compared to the previous layer, in mm^2
+}
+
+/// Number of pixel edges between lit and unlit pixels, counting the image border as unlit.
+fn outline_edges(bitmap: &Bitmap) -> usize {
+    let mut edges = 0;
+    for y in 0..bitmap.height {
+        for x in 0..bitmap.width {
+            if !bitmap.get(x, y) {
+                continue;
+            }
+            let neighbours = [
+                x > 0 && bitmap.get(x - 1, y),
+                x + 1 < bitmap.width && bitmap.get(x + 1, y),
+                y > 0 && bitmap.get(x, y - 1),
+                y + 1 < bitmap.height && bitmap.get(x, y + 1),
+            ];
+            edges += neighbours.iter().filter(|lit| !**lit).count();
+        }
+    }
+    edges
+}
+
+/// Profile of a single decoded layer. `z` and `area_change` are left for the caller to fill in.
+pub fn image_profile(layer: usize, image: &GrayImage, pixel_size: f32) -> LayerProfile {
+    let bitmap = Bitmap::from_image(image);
+    let largest = label(&bitmap, true)
+        .components
+        .iter()
+        .map(|component| component.area)
+        .max()
+        .unwrap_or(0);
+    LayerProfile {
+        layer,
+        z: 0.0,
+        area: image_area(image, pixel_size),
+        perimeter: outline_edges(&bitmap) as f32 * pixel_size,
+        largest_component: largest as f32 * pixel_size * pixel_size,
+        bounds: layer_bounds(image),
+        area_change: 0.0,
+    }
+}
+
+/// Profiles of all layers, in order.
+pub fn profile_job<F>(file: &PwsFile, on_layer: F) -> Vec<LayerProfile>
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let mut profiles: Vec<LayerProfile> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let profile = image_profile(index, &file.decode_layer(index), pixel_size);
+            on_layer(index);
+            profile
+        })
+        .collect();
+    let mut z = 0.0;
+    let mut previous_area = 0.0;
+    for profile in profiles.iter_mut() {
+        z += file.layer_thickness(profile.layer);
+        profile.z = z;
+        profile.area_change = profile.area - previous_area;
+        previous_area = profile.area;
+    }
+    profiles
+}
+
+#[test]
+fn test_image_profile() {
+    // A 2x2 square and a separate single pixel
+    let image =
+        GrayImage::from_raw(4, 3, vec![255, 255, 0, 0, 255, 255, 0, 255, 0, 0, 0, 0]).unwrap();
+    let profile = image_profile(0, &image, 0.5);
+    assert_eq!(profile.area, 5.0 * 0.25);
+    assert_eq!(profile.perimeter, (8.0 + 4.0) * 0.5);
+    assert_eq!(profile.largest_component, 4.0 * 0.25);
+    assert_eq!(profile.bounds.unwrap().width(), 4);
+}
@@ -0,0 +1,22 @@
+use crate::formats::pws::data::PwsFile;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Lit area of a decoded layer in mm^2, with anti-aliased pixels counting partially.
+pub fn image_area(image: &GrayImage, pixel_size: f32) -> f32 {
+    let lit: u64 = image.pixels().map(|p| p.0[0] as u64).sum();
+    ((lit as f64 / 255.0) * (pixel_size * pixel_size) as f64) as f32
+}
+
+/// Resin volume in mm^3, with anti-aliased pixels counting partially.
+pub fn compute_volume(file: &PwsFile) -> f32 {
+    let pixel_size = file.header.pixel_size_mm();
+    let volume: f64 = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let image = file.decode_layer(index);
+            image_area(&image, pixel_size) as f64 * file.layer_thickness(index) as f64
+        })
+        .sum();
+    volume as f32
+}
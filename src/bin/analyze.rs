@@ -0,0 +1,446 @@
+use clap::{crate_version, App, Arg, ArgMatches, SubCommand};
+use pbr::ProgressBar;
+use sla_format_tools::analysis::{cavities, features, fit, islands, lint, profile};
+use sla_format_tools::formats::job;
+use sla_format_tools::printer::Printer;
+use sla_format_tools::report::{Chart, Series, Table, Value};
+use std::path::Path;
+use std::sync::Mutex;
+
+fn check_parse_arg<T: std::str::FromStr>(input: String) -> Result<(), String>
+where
+    T::Err: std::string::ToString,
+{
+    input.parse::<T>().map(drop).map_err(|e| e.to_string())
+}
+
+fn check_printer_arg(input: String) -> Result<(), String> {
+    match Printer::by_name(&input) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Unknown printer, allowed values are {}",
+            Printer::names().join(", ")
+        )),
+    }
+}
+
+fn input_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("input")
+        .short("i")
+        .long("input")
+        .value_name("filename")
+        .help("Input .pws or .photons file")
+        .required(true)
+        .takes_value(true)
+}
+
+fn report_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("report")
+        .short("r")
+        .long("report")
+        .value_name("filename")
+        .help("Write report to .csv or .json file")
+        .takes_value(true)
+}
+
+fn save_report(args: &ArgMatches, table: &Table) {
+    if let Some(report) = args.value_of("report") {
+        table.save(Path::new(report)).unwrap();
+    }
+}
+
+fn run_islands(args: &ArgMatches) {
+    let file = job::read_job(Path::new(args.value_of("input").unwrap())).unwrap();
+    let png_dir = args.value_of("png-dir").map(Path::new);
+    if let Some(png_dir) = png_dir {
+        std::fs::create_dir_all(png_dir).unwrap();
+    }
+    let mut pb = ProgressBar::new(file.layers.len().saturating_sub(1) as u64);
+    pb.message("Finding islands: ");
+    let pb = Mutex::new(pb);
+    let found = islands::find_islands(&file, |index, image, layer_islands| {
+        if let Some(png_dir) = png_dir {
+            if !layer_islands.islands.is_empty() {
+                islands::mark_islands(image, layer_islands)
+                    .save(png_dir.join(format!("island{:05}.png", index)))
+                    .unwrap();
+            }
+        }
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+
+    let mut table = Table::new(vec!["layer", "x_mm", "y_mm", "area_mm2", "pixels"]);
+    for island in found.iter() {
+        println!(
+            "Layer {}: island at ({:.2}, {:.2}) mm, {:.3} mm^2",
+            island.layer, island.centroid.0, island.centroid.1, island.area
+        );
+        table.push(vec![
+            island.layer.into(),
+            island.centroid.0.into(),
+            island.centroid.1.into(),
+            island.area.into(),
+            island.pixels.into(),
+        ]);
+    }
+    println!("{} islands found", found.len());
+    save_report(args, &table);
+}
+
+fn run_cavities(args: &ArgMatches) {
+    let file = job::read_job(Path::new(args.value_of("input").unwrap())).unwrap();
+    let mut pb = ProgressBar::new(file.layers.len() as u64);
+    pb.message("Finding cavities: ");
+    let pb = Mutex::new(pb);
+    let found = cavities::find_cavities(&file, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+
+    let pixel_size = file.header.pixel_size_mm();
+    let mut table = Table::new(vec![
+        "kind",
+        "first_layer",
+        "last_layer",
+        "volume_mm3",
+        "bottom_x_mm",
+        "bottom_y_mm",
+        "min_x_mm",
+        "min_y_mm",
+        "max_x_mm",
+        "max_y_mm",
+    ]);
+    for cavity in found.iter() {
+        let kind = if cavity.open_top {
+            "suction-cup"
+        } else {
+            "resin-trap"
+        };
+        println!(
+            "{} in layers {}-{}: {:.2} mm^3, lowest point at ({:.2}, {:.2}) mm",
+            kind,
+            cavity.first_layer,
+            cavity.last_layer,
+            cavity.volume,
+            cavity.bottom.0,
+            cavity.bottom.1
+        );
+        table.push(vec![
+            kind.into(),
+            cavity.first_layer.into(),
+            cavity.last_layer.into(),
+            cavity.volume.into(),
+            cavity.bottom.0.into(),
+            cavity.bottom.1.into(),
+            (cavity.bounds.min_x as f32 * pixel_size).into(),
+            (cavity.bounds.min_y as f32 * pixel_size).into(),
+            ((cavity.bounds.max_x + 1) as f32 * pixel_size).into(),
+            ((cavity.bounds.max_y + 1) as f32 * pixel_size).into(),
+        ]);
+    }
+    println!("{} cavities found", found.len());
+    save_report(args, &table);
+}
+
+fn run_thin(args: &ArgMatches) {
+    let file = job::read_job(Path::new(args.value_of("input").unwrap())).unwrap();
+    let limits = features::FeatureLimits {
+        min_width: args.value_of("min-width").unwrap().parse().unwrap(),
+        min_pin_area: args.value_of("min-pin-area").unwrap().parse().unwrap(),
+    };
+    let mut pb = ProgressBar::new(file.layers.len() as u64);
+    pb.message("Finding thin features: ");
+    let pb = Mutex::new(pb);
+    let mut found = features::find_thin_features(&file, &limits, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    found.sort_by_key(|feature| feature.layer);
+
+    let pixel_size = file.header.pixel_size_mm();
+    let mut table = Table::new(vec![
+        "kind", "layer", "x_mm", "y_mm", "area_mm2", "min_x_mm", "min_y_mm", "max_x_mm", "max_y_mm",
+    ]);
+    for feature in found.iter() {
+        println!(
+            "Layer {}: {} at ({:.2}, {:.2}) mm, {:.3} mm^2",
+            feature.layer,
+            feature.kind.name(),
+            feature.centroid.0,
+            feature.centroid.1,
+            feature.area
+        );
+        table.push(vec![
+            feature.kind.name().into(),
+            feature.layer.into(),
+            feature.centroid.0.into(),
+            feature.centroid.1.into(),
+            feature.area.into(),
+            (feature.bounds.min_x as f32 * pixel_size).into(),
+            (feature.bounds.min_y as f32 * pixel_size).into(),
+            ((feature.bounds.max_x + 1) as f32 * pixel_size).into(),
+            ((feature.bounds.max_y + 1) as f32 * pixel_size).into(),
+        ]);
+    }
+    println!("{} thin features found", found.len());
+    save_report(args, &table);
+}
+
+fn run_fit(args: &ArgMatches) {
+    let file = job::read_job(Path::new(args.value_of("input").unwrap())).unwrap();
+    let printer = Printer::by_name(args.value_of("printer").unwrap()).unwrap();
+    let mut pb = ProgressBar::new(file.layers.len() as u64);
+    pb.message("Measuring part: ");
+    let pb = Mutex::new(pb);
+    let extent = fit::measure_extent(&file, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+
+    let pixel_size = file.header.pixel_size_mm();
+    let height: f32 = (0..file.layers.len())
+        .map(|index| file.layer_thickness(index))
+        .sum();
+    match extent.bounds {
+        Some(bounds) => println!(
+            "Occupied area {:.2} x {:.2} mm, from ({:.2}, {:.2}) to ({:.2}, {:.2}) mm, \
+             {:.2} mm high",
+            bounds.width() as f32 * pixel_size,
+            bounds.height() as f32 * pixel_size,
+            bounds.min_x as f32 * pixel_size,
+            bounds.min_y as f32 * pixel_size,
+            (bounds.max_x + 1) as f32 * pixel_size,
+            (bounds.max_y + 1) as f32 * pixel_size,
+            height
+        ),
+        None => println!("All layers are empty, {:.2} mm high", height),
+    }
+    let problems = fit::check_fit(&file, &extent, printer);
+    for problem in problems.iter() {
+        println!("Warning: {}", problem);
+    }
+    if problems.is_empty() {
+        println!("Job fits the {}", printer.name);
+    }
+}
+
+fn run_lint(args: &ArgMatches) {
+    let file = job::read_job_unchecked(Path::new(args.value_of("input").unwrap())).unwrap();
+    let issues = lint::lint_job(&file);
+    let mut table = Table::new(vec!["severity", "message", "layer_count", "layers"]);
+    for issue in issues.iter() {
+        let layers: Vec<String> = issue.layers.iter().map(|layer| layer.to_string()).collect();
+        match layers.len() {
+            0 => println!("{}: {}", issue.severity.name(), issue.message),
+            1..=10 => println!(
+                "{}: {} (layers {})",
+                issue.severity.name(),
+                issue.message,
+                layers.join(", ")
+            ),
+            count => println!(
+                "{}: {} (layers {} and {} more)",
+                issue.severity.name(),
+                issue.message,
+                layers[..10].join(", "),
+                count - 10
+            ),
+        }
+        table.push(vec![
+            issue.severity.name().into(),
+            issue.message.clone().into(),
+            issue.layers.len().into(),
+            layers.join(" ").into(),
+        ]);
+    }
+    let errors = issues
+        .iter()
+        .filter(|issue| issue.severity == lint::Severity::Error)
+        .count();
+    println!("{} errors, {} warnings", errors, issues.len() - errors);
+    save_report(args, &table);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+fn run_profile(args: &ArgMatches) {
+    let file = job::read_job(Path::new(args.value_of("input").unwrap())).unwrap();
+    let mut pb = ProgressBar::new(file.layers.len() as u64);
+    pb.message("Profiling layers: ");
+    let pb = Mutex::new(pb);
+    let profiles = profile::profile_job(&file, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+
+    let pixel_size = file.header.pixel_size_mm();
+    let mut table = Table::new(vec![
+        "layer",
+        "z_mm",
+        "area_mm2",
+        "perimeter_mm",
+        "largest_component_mm2",
+        "area_change_mm2",
+        "min_x_mm",
+        "min_y_mm",
+        "max_x_mm",
+        "max_y_mm",
+    ]);
+    for profile in profiles.iter() {
+        let bounds: Vec<Value> = match profile.bounds {
+            Some(bounds) => vec![
+                (bounds.min_x as f32 * pixel_size).into(),
+                (bounds.min_y as f32 * pixel_size).into(),
+                ((bounds.max_x + 1) as f32 * pixel_size).into(),
+                ((bounds.max_y + 1) as f32 * pixel_size).into(),
+            ],
+            None => vec![f32::NAN.into(); 4],
+        };
+        let mut row: Vec<Value> = vec![
+            profile.layer.into(),
+            profile.z.into(),
+            profile.area.into(),
+            profile.perimeter.into(),
+            profile.largest_component.into(),
+            profile.area_change.into(),
+        ];
+        row.extend(bounds);
+        table.push(row);
+    }
+    if let Some(peak) = profiles
+        .iter()
+        .max_by(|a, b| a.area.partial_cmp(&b.area).unwrap())
+    {
+        println!(
+            "Largest cross-section: {:.2} mm^2 at layer {} ({:.2} mm)",
+            peak.area, peak.layer, peak.z
+        );
+    }
+    // The first layer always grows from nothing
+    if let Some(jump) = profiles
+        .iter()
+        .skip(1)
+        .max_by(|a, b| a.area_change.partial_cmp(&b.area_change).unwrap())
+    {
+        println!(
+            "Largest area increase: {:.2} mm^2 at layer {} ({:.2} mm)",
+            jump.area_change, jump.layer, jump.z
+        );
+    }
+    save_report(args, &table);
+
+    if let Some(chart) = args.value_of("chart") {
+        let series = |name: &str, value: fn(&profile::LayerProfile) -> f32| Series {
+            name: name.to_string(),
+            values: profiles.iter().map(value).collect(),
+        };
+        let chart_data = Chart {
+            x_label: "Height (mm)".to_string(),
+            x_values: profiles.iter().map(|profile| profile.z).collect(),
+            series: vec![
+                series("Area (mm2)", |profile| profile.area),
+                series("Area change (mm2)", |profile| profile.area_change),
+                series("Largest region (mm2)", |profile| profile.largest_component),
+                series("Perimeter (mm)", |profile| profile.perimeter),
+            ],
+        };
+        chart_data.save(Path::new(chart)).unwrap();
+    }
+}
+
+fn main() {
+    let args = App::new("SLA print job analyzer")
+        .version(crate_version!())
+        .author("Frans-willem Hardijzer <fw@hardijzer.nl>")
+        .about("Checks sliced print jobs for likely print failures")
+        .subcommand(
+            SubCommand::with_name("islands")
+                .about("Finds regions that have nothing beneath them in the previous layer")
+                .arg(input_arg())
+                .arg(report_arg())
+                .arg(
+                    Arg::with_name("png-dir")
+                        .long("png-dir")
+                        .value_name("directory")
+                        .help("Write layers containing islands as marked-up .png files")
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cavities")
+                .about("Finds enclosed empty volumes that form suction cups or trap resin")
+                .arg(input_arg())
+                .arg(report_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("thin")
+                .about("Finds walls and pins too thin to cure or survive lifting")
+                .arg(input_arg())
+                .arg(report_arg())
+                .arg(
+                    Arg::with_name("min-width")
+                        .long("min-width")
+                        .value_name("mm")
+                        .help("Narrowest wall that cures reliably")
+                        .default_value("0.2")
+                        .validator(check_parse_arg::<f32>)
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("min-pin-area")
+                        .long("min-pin-area")
+                        .value_name("mm^2")
+                        .help("Smallest cross-section that carries later layers through lifting")
+                        .default_value("0.1")
+                        .validator(check_parse_arg::<f32>)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fit")
+                .about("Checks that the job fits the screen and Z travel of a printer")
+                .arg(input_arg())
+                .arg(
+                    Arg::with_name("printer")
+                        .short("p")
+                        .long("printer")
+                        .value_name("name")
+                        .help("Printer to check against")
+                        .validator(check_printer_arg)
+                        .required(true)
+                        .takes_value(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lint")
+                .about("Checks print parameters and layer data for inconsistent values")
+                .arg(input_arg())
+                .arg(report_arg()),
+        )
+        .subcommand(
+            SubCommand::with_name("profile")
+                .about("Reports the cross-section area and outline of every layer")
+                .arg(input_arg())
+                .arg(report_arg())
+                .arg(
+                    Arg::with_name("chart")
+                        .long("chart")
+                        .value_name("filename")
+                        .help("Write a chart of the profile to .svg or .png file")
+                        .takes_value(true),
+                ),
+        )
+        .get_matches();
+
+    match args.subcommand() {
+        ("islands", Some(sub_args)) => run_islands(sub_args),
+        ("cavities", Some(sub_args)) => run_cavities(sub_args),
+        ("thin", Some(sub_args)) => run_thin(sub_args),
+        ("fit", Some(sub_args)) => run_fit(sub_args),
+        ("lint", Some(sub_args)) => run_lint(sub_args),
+        ("profile", Some(sub_args)) => run_profile(sub_args),
+        _ => println!("{}", args.usage()),
+    }
+}
@@ -0,0 +1,298 @@
+use clap::{crate_version, App, Arg, ArgMatches, SubCommand};
+use image::GenericImageView;
+use pbr::ProgressBar;
+use sla_format_tools::formats::job::{self, FileFormat};
+use sla_format_tools::generate::{calibration, lithophane, PrintSettings};
+use sla_format_tools::printer::Printer;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+fn check_parse_arg<T: std::str::FromStr>(input: String) -> Result<(), String>
+where
+    T::Err: std::string::ToString,
+{
+    input.parse::<T>().map(drop).map_err(|e| e.to_string())
+}
+
+fn check_list_arg(input: String) -> Result<(), String> {
+    input
+        .split(',')
+        .try_for_each(|v| match v.trim().parse::<f32>() {
+            Ok(v) if v.is_finite() => Ok(()),
+            Ok(v) => Err(format!("{} is not a finite number", v)),
+            Err(e) => Err(e.to_string()),
+        })
+}
+
+fn check_printer_arg(input: String) -> Result<(), String> {
+    match Printer::by_name(&input) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Unknown printer, allowed values are {}",
+            Printer::names().join(", ")
+        )),
+    }
+}
+
+fn output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("filename")
+        .help("Output .pws or .photons file")
+        .required(true)
+        .takes_value(true)
+}
+
+/// Arguments for the print settings shared by all generators.
+fn print_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("printer")
+            .short("p")
+            .long("printer")
+            .value_name("name")
+            .default_value("photon-s")
+            .validator(check_printer_arg)
+            .help("Printer to generate the job for"),
+        Arg::with_name("layer-height")
+            .long("layer-height")
+            .value_name("mm")
+            .default_value("0.05")
+            .validator(check_parse_arg::<f32>),
+        Arg::with_name("bottom-exposure")
+            .long("bottom-exposure")
+            .value_name("seconds")
+            .default_value("60")
+            .validator(check_parse_arg::<f32>),
+        Arg::with_name("bottom-layers")
+            .long("bottom-layers")
+            .value_name("count")
+            .default_value("6")
+            .validator(check_parse_arg::<usize>),
+    ]
+}
+
+fn print_settings(args: &ArgMatches) -> (&'static Printer, PrintSettings) {
+    let printer = Printer::by_name(args.value_of("printer").unwrap()).unwrap();
+    let settings = PrintSettings {
+        layer_height: args.value_of("layer-height").unwrap().parse().unwrap(),
+        bottom_exposure_time: args.value_of("bottom-exposure").unwrap().parse().unwrap(),
+        bottom_layers: args.value_of("bottom-layers").unwrap().parse().unwrap(),
+        ..PrintSettings::default()
+    };
+    (printer, settings)
+}
+
+fn progress_bar(count: usize, message: &str) -> Mutex<ProgressBar<std::io::Stdout>> {
+    let mut pb = ProgressBar::new(count as u64);
+    pb.message(message);
+    Mutex::new(pb)
+}
+
+/// Output path for the job of a single exposure time, "test.photons" becomes "test-8s.photons".
+fn exposure_path(output: &Path, exposure_time: f32) -> PathBuf {
+    let stem = output.file_stem().unwrap_or_default().to_string_lossy();
+    let mut name = format!("{}-{}s", stem, exposure_time);
+    if let Some(extension) = output.extension() {
+        name = format!("{}.{}", name, extension.to_string_lossy());
+    }
+    output.with_file_name(name)
+}
+
+fn run_calibration(args: &ArgMatches) {
+    let (printer, print) = print_settings(args);
+    let output = Path::new(args.value_of("output").unwrap());
+    let settings = calibration::Calibration {
+        exposure_times: args
+            .value_of("exposure-times")
+            .unwrap()
+            .split(',')
+            .map(|v| v.trim().parse().unwrap())
+            .collect(),
+        base_height: args.value_of("base-height").unwrap().parse().unwrap(),
+        feature_height: args.value_of("feature-height").unwrap().parse().unwrap(),
+    };
+    // Only PWS has the per-layer parameters needed to expose cells separately, other formats get
+    // a job with a single cell for every exposure time.
+    let sub_exposures = FileFormat::from_path(output) == Some(FileFormat::Pws);
+    let jobs: Vec<(PathBuf, calibration::Calibration)> = if sub_exposures {
+        vec![(output.to_path_buf(), settings)]
+    } else {
+        println!("Output format has no per-layer parameters, writing one job per exposure time");
+        settings
+            .exposure_times
+            .iter()
+            .map(|time| {
+                let single = calibration::Calibration {
+                    exposure_times: vec![*time],
+                    ..settings.clone()
+                };
+                (exposure_path(output, *time), single)
+            })
+            .collect()
+    };
+    for (path, settings) in jobs.iter() {
+        let num_layers =
+            ((settings.base_height + settings.feature_height) / print.layer_height).ceil();
+        let pb = progress_bar(
+            num_layers as usize,
+            &format!("Generating {}: ", path.display()),
+        );
+        let file = calibration::generate_calibration(printer, &print, settings, |_| {
+            pb.lock().unwrap().inc();
+        });
+        pb.lock().unwrap().finish_print("Done");
+        match file {
+            Ok(file) => job::write_job(path, &file).unwrap(),
+            Err(e) => {
+                println!("Can not generate calibration job: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+fn run_lithophane(args: &ArgMatches) {
+    let (printer, mut print) = print_settings(args);
+    print.exposure_time = args.value_of("exposure").unwrap().parse().unwrap();
+    let image = match image::open(args.value_of("input").unwrap()) {
+        Ok(image) => image,
+        Err(e) => {
+            println!("Can not read image: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let settings = lithophane::Lithophane {
+        width: args.value_of("width").unwrap().parse().unwrap(),
+        min_thickness: args.value_of("min-thickness").unwrap().parse().unwrap(),
+        max_thickness: args.value_of("max-thickness").unwrap().parse().unwrap(),
+        frame: args.value_of("frame").unwrap().parse().unwrap(),
+        shape: match args.value_of("curve") {
+            Some(angle) => lithophane::Shape::Curved {
+                angle: angle.parse().unwrap(),
+            },
+            None => lithophane::Shape::Flat,
+        },
+    };
+    let height = settings.height(image.width(), image.height());
+    let pb = progress_bar(
+        (height / print.layer_height).ceil() as usize,
+        "Generating layers: ",
+    );
+    let file = lithophane::generate_lithophane(printer, &print, &image, &settings, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    match file {
+        Ok(file) => job::write_job(Path::new(args.value_of("output").unwrap()), &file).unwrap(),
+        Err(e) => {
+            println!("Can not generate lithophane: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn main() {
+    let args = App::new("SLA print job generator")
+        .version(crate_version!())
+        .author("Frans-willem Hardijzer <fw@hardijzer.nl>")
+        .about("Builds print jobs without a slicer")
+        .subcommand(
+            SubCommand::with_name("calibration")
+                .about("Exposure test with a cell of test features per exposure time")
+                .arg(output_arg())
+                .args(&print_args())
+                .arg(
+                    Arg::with_name("exposure-times")
+                        .short("e")
+                        .long("exposure-times")
+                        .value_name("seconds,...")
+                        .required(true)
+                        .validator(check_list_arg)
+                        .help("Comma separated exposure times to test"),
+                )
+                .arg(
+                    Arg::with_name("base-height")
+                        .long("base-height")
+                        .value_name("mm")
+                        .default_value("1.0")
+                        .validator(check_parse_arg::<f32>),
+                )
+                .arg(
+                    Arg::with_name("feature-height")
+                        .long("feature-height")
+                        .value_name("mm")
+                        .default_value("3.0")
+                        .validator(check_parse_arg::<f32>),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lithophane")
+                .about("Panel with a photo as relief, thicker where the photo is darker")
+                .arg(
+                    Arg::with_name("input")
+                        .short("i")
+                        .long("input")
+                        .value_name("filename")
+                        .help("Input image")
+                        .required(true)
+                        .takes_value(true),
+                )
+                .arg(output_arg())
+                .args(&print_args())
+                .arg(
+                    Arg::with_name("exposure")
+                        .long("exposure")
+                        .value_name("seconds")
+                        .default_value("8")
+                        .validator(check_parse_arg::<f32>),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .short("w")
+                        .long("width")
+                        .value_name("mm")
+                        .default_value("50")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Width of the image, the height follows its aspect ratio"),
+                )
+                .arg(
+                    Arg::with_name("min-thickness")
+                        .long("min-thickness")
+                        .value_name("mm")
+                        .default_value("0.8")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Thickness of white"),
+                )
+                .arg(
+                    Arg::with_name("max-thickness")
+                        .long("max-thickness")
+                        .value_name("mm")
+                        .default_value("3.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Thickness of black, and of the frame"),
+                )
+                .arg(
+                    Arg::with_name("frame")
+                        .long("frame")
+                        .value_name("mm")
+                        .default_value("0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Width of the frame around the image"),
+                )
+                .arg(
+                    Arg::with_name("curve")
+                        .long("curve")
+                        .value_name("degrees")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Print upright, bent around this angle, instead of flat"),
+                ),
+        )
+        .get_matches();
+
+    match args.subcommand() {
+        ("calibration", Some(sub_args)) => run_calibration(sub_args),
+        ("lithophane", Some(sub_args)) => run_lithophane(sub_args),
+        _ => println!("{}", args.usage()),
+    }
+}
@@ -4,6 +4,12 @@ use ini;
 use pbr::ProgressBar;
 use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
 use sla_format_tools::formats::pws;
+use sla_format_tools::formats::pws::transfer::TransferFunction;
+use sla_format_tools::printer::Printer;
+use sla_format_tools::raster::antialias::{synthesize_antialiasing, Antialias};
+use sla_format_tools::raster::dither::Dither;
+use sla_format_tools::raster::resample::resample;
+use sla_format_tools::transform::elephant_foot::ElephantFoot;
 use std::collections::HashSet;
 use std::fs::File;
 use std::io::Read;
@@ -24,6 +30,33 @@ where
     input.parse::<T>().map(drop).map_err(|e| e.to_string())
 }
 
+fn check_printer_arg(input: String) -> Result<(), String> {
+    match Printer::by_name(&input) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Unknown printer, allowed values are {}",
+            Printer::names().join(", ")
+        )),
+    }
+}
+
+/// Pixel size of the source printer in um, from the PrusaSlicer settings if present.
+fn sl1_pixel_size(sl1file: &mut zip::read::ZipArchive<File>) -> f32 {
+    let default_pixel_size = Printer::by_name("sl1").unwrap().pixel_size;
+    let config = match sl1file.by_name("prusaslicer.ini") {
+        Ok(mut config_file) => ini::Ini::read_from(&mut config_file).ok(),
+        Err(_) => None,
+    };
+    config
+        .and_then(|config| {
+            let section = config.general_section();
+            let display_width = section.get("display_width")?.parse::<f32>().ok()?;
+            let display_pixels_x = section.get("display_pixels_x")?.parse::<f32>().ok()?;
+            Some(display_width / display_pixels_x * 1000.0)
+        })
+        .unwrap_or(default_pixel_size)
+}
+
 fn iterate_sl1_layers(
     sl1file: zip::read::ZipArchive<File>,
     job_dir: String,
@@ -50,40 +83,57 @@ fn iterate_sl1_layers(
     })
 }
 
-fn convert_sl1_layers(
-    sl1file: zip::read::ZipArchive<File>,
-    job_dir: String,
-    num_layers: usize,
+/// Encoding and print parameters applied to every converted layer.
+struct LayerSettings {
     bits_per_pixel: usize,
+    transfer: TransferFunction,
+    dither_method: Option<Dither>,
     layer_height: f32,
     lift_distance: f32,
     lift_speed: f32,
     exposure_time: f32,
     bottom_exposure_time: f32,
     num_slow: usize,
-    num_fade: usize,
+    num_fade: usize, // Layers over which the exposure goes from bottom to normal
+}
+
+fn convert_sl1_layers(
+    sl1file: zip::read::ZipArchive<File>,
+    job_dir: String,
+    num_layers: usize,
+    settings: &LayerSettings,
+    process_layer: &(dyn Fn(usize, GrayImage) -> GrayImage + Sync),
 ) -> (HashSet<(u32, u32)>, Vec<pws::data::PwsLayer>) {
     let layer_images = iterate_sl1_layers(sl1file, job_dir, num_layers);
     let pb = Mutex::new(ProgressBar::new(num_layers as u64));
     pb.lock().unwrap().message("Converting layers: ");
     let layer_compressed = layer_images.enumerate().map(|(index, image)| {
-        let data = pws::data::CompressedBitstream::from_image(&image, bits_per_pixel);
+        let image = process_layer(index, image);
+        let data = pws::data::CompressedBitstream::from_image_with(
+            &image,
+            settings.bits_per_pixel,
+            &settings.transfer,
+            settings.dither_method,
+        );
+        let (num_slow, num_fade) = (settings.num_slow, settings.num_fade);
         let exposure_time = if index < num_slow {
-            bottom_exposure_time
+            settings.bottom_exposure_time
         } else if index < (num_slow + num_fade) {
             let fade: f32 = (index - num_slow) as f32 / num_fade as f32;
-            exposure_time.mul_add(fade, bottom_exposure_time * (1.0 - fade))
+            settings
+                .exposure_time
+                .mul_add(fade, settings.bottom_exposure_time * (1.0 - fade))
         } else {
-            exposure_time
+            settings.exposure_time
         };
         pb.lock().unwrap().inc();
         (
             (image.width(), image.height()),
             pws::data::PwsLayer {
-                lift_distance,
-                lift_speed,
+                lift_distance: settings.lift_distance,
+                lift_speed: settings.lift_speed,
                 exposure_time,
-                layer_height,
+                layer_height: settings.layer_height,
                 data,
             },
         )
@@ -153,6 +203,92 @@ fn main() {
                 .validator(check_parse_arg::<f32>)
                 .help("Drop speed in millimeter per second"),
         )
+        .arg(
+            Arg::with_name("elephant-foot")
+                .long("elephant-foot")
+                .value_name("mm")
+                .validator(check_parse_arg::<f32>)
+                .help("Shrink the bottom layers by this distance in millimeter"),
+        )
+        .arg(
+            Arg::with_name("elephant-foot-layers")
+                .long("elephant-foot-layers")
+                .value_name("count")
+                .validator(check_parse_arg::<usize>)
+                .help("Number of layers to shrink, defaults to the bottom layers"),
+        )
+        .arg(
+            Arg::with_name("elephant-foot-taper")
+                .long("elephant-foot-taper")
+                .help("Linearly reduce the shrink distance to zero over the shrunk layers"),
+        )
+        .arg(
+            Arg::with_name("printer")
+                .short("p")
+                .long("printer")
+                .value_name("name")
+                .default_value("photon-s")
+                .validator(check_printer_arg)
+                .help("Target printer, layers are resampled if its pixel grid differs"),
+        )
+        .arg(
+            Arg::with_name("synthesize-aa")
+                .long("synthesize-aa")
+                .value_name("method")
+                .possible_values(&["blur", "supersample"])
+                .help("Add anti-aliasing to layers that are pure black and white"),
+        )
+        .arg(
+            Arg::with_name("aa-radius")
+                .long("aa-radius")
+                .value_name("pixels")
+                .default_value("1")
+                .validator(check_parse_arg::<u32>)
+                .help("Blur radius for synthesized anti-aliasing"),
+        )
+        .arg(
+            Arg::with_name("aa-factor")
+                .long("aa-factor")
+                .value_name("count")
+                .default_value("4")
+                .validator(check_parse_arg::<u32>)
+                .help("Samples per pixel along each axis for supersampled anti-aliasing"),
+        )
+        .arg(
+            Arg::with_name("dither")
+                .long("dither")
+                .value_name("method")
+                .possible_values(&["floyd-steinberg", "bayer"])
+                .help("Dither grey values down to the anti-aliasing levels instead of rounding"),
+        )
+        .arg(
+            Arg::with_name("gamma")
+                .long("gamma")
+                .value_name("gamma")
+                .validator(check_parse_arg::<f32>)
+                .help("Power curve applied to grey values before anti-aliasing"),
+        )
+        .arg(
+            Arg::with_name("min-grey")
+                .long("min-grey")
+                .value_name("value")
+                .validator(check_parse_arg::<u8>)
+                .help("Lowest grey value of lit pixels, after the curve"),
+        )
+        .arg(
+            Arg::with_name("max-grey")
+                .long("max-grey")
+                .value_name("value")
+                .validator(check_parse_arg::<u8>)
+                .help("Highest grey value of lit pixels, after the curve"),
+        )
+        .arg(
+            Arg::with_name("lut")
+                .long("lut")
+                .value_name("filename")
+                .conflicts_with_all(&["gamma", "min-grey", "max-grey"])
+                .help("CSV file with input,output grey value pairs to map through"),
+        )
         .get_matches();
 
     let input_fname = args.value_of("input").unwrap();
@@ -182,26 +318,110 @@ fn main() {
     let lift_speed = args.value_of("lift-speed").unwrap().parse::<f32>().unwrap();
     let drop_speed = args.value_of("drop-speed").unwrap().parse::<f32>().unwrap();
 
+    let transfer = match args.value_of("lut") {
+        Some(path) => match std::fs::read_to_string(path)
+            .map_err(|e| e.to_string())
+            .and_then(|contents| TransferFunction::from_csv(&contents))
+        {
+            Ok(transfer) => transfer,
+            Err(e) => {
+                println!("Can not read transfer function {}: {}", path, e);
+                std::process::exit(1);
+            }
+        },
+        None => TransferFunction::gamma(
+            args.value_of("gamma").map_or(1.0, |v| v.parse().unwrap()),
+            args.value_of("min-grey").map_or(0, |v| v.parse().unwrap()),
+            args.value_of("max-grey")
+                .map_or(255, |v| v.parse().unwrap()),
+        ),
+    };
+
+    let dither_method = args.value_of("dither").map(|method| match method {
+        "bayer" => Dither::Bayer,
+        _ => Dither::FloydSteinberg,
+    });
+
+    let printer = Printer::by_name(args.value_of("printer").unwrap()).unwrap();
+    let source_pixel_size = sl1_pixel_size(&mut z);
+    let pixel_size = printer.pixel_size;
+    let elephant_foot = args.value_of("elephant-foot").map(|distance| ElephantFoot {
+        distance: distance.parse().unwrap(),
+        num_layers: match args.value_of("elephant-foot-layers") {
+            Some(layers) => layers.parse().unwrap(),
+            None => (num_slow + num_fade) as usize,
+        },
+        taper: args.is_present("elephant-foot-taper"),
+    });
+
+    let synthesize_aa = args.value_of("synthesize-aa").map(|method| match method {
+        "blur" => Antialias::Blur {
+            radius: args.value_of("aa-radius").unwrap().parse().unwrap(),
+        },
+        _ => Antialias::Supersample {
+            factor: args.value_of("aa-factor").unwrap().parse().unwrap(),
+        },
+    });
+
+    let process_layer = |index: usize, image: GrayImage| {
+        let image = if (image.width(), image.height(), source_pixel_size)
+            != (printer.width, printer.height, pixel_size)
+        {
+            let (image, clipped) = resample(
+                &image,
+                source_pixel_size,
+                printer.width,
+                printer.height,
+                pixel_size,
+            );
+            if clipped > 0 {
+                println!(
+                    "Warning: layer {} lost {} lit pixels off the edge of the plate",
+                    index, clipped
+                );
+            }
+            image
+        } else {
+            image
+        };
+        let image = match &elephant_foot {
+            Some(settings) => settings
+                .apply_to_layer(index, &image, pixel_size / 1000.0)
+                .unwrap_or(image),
+            None => image,
+        };
+        match synthesize_aa {
+            Some(method) => synthesize_antialiasing(&image, method),
+            None => image,
+        }
+    };
+
     let preview = RgbImage::from_pixel(224, 168, Rgb([0, 0, 0]));
-    let (sizes, layers) = convert_sl1_layers(
-        z,
-        config.general_section()["jobDir"].clone(),
-        num_total as usize,
-        bits_per_pixel as usize,
+    let settings = LayerSettings {
+        bits_per_pixel: bits_per_pixel as usize,
+        transfer,
+        dither_method,
         layer_height,
         lift_distance,
         lift_speed,
         exposure_time,
-        exposure_time_first,
-        num_slow as usize,
-        num_fade as usize,
+        bottom_exposure_time: exposure_time_first,
+        num_slow: num_slow as usize,
+        num_fade: num_fade as usize,
+    };
+    let (sizes, layers) = convert_sl1_layers(
+        z,
+        config.general_section()["jobDir"].clone(),
+        num_total as usize,
+        &settings,
+        &process_layer,
     );
     if sizes.len() != 1 {
         panic!("Sizes do not match between layers!");
     }
     let size = sizes.into_iter().next().unwrap();
     let header = pws::data::PwsHeader {
-        pixel_size: 47.25, //0.04725,
+        pixel_size,
         layer_height,
         exposure_time,
         off_time: 1.0,
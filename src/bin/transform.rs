@@ -0,0 +1,1072 @@
+use clap::{crate_version, App, Arg, ArgGroup, ArgMatches, SubCommand};
+use pbr::ProgressBar;
+use sla_format_tools::analysis::{bounds, cavities};
+use sla_format_tools::formats::job;
+use sla_format_tools::formats::pws::data::PwsFile;
+use sla_format_tools::printer::Printer;
+use sla_format_tools::raster::antialias::Antialias;
+use sla_format_tools::transform::geometry::Geometry;
+use sla_format_tools::transform::{
+    adaptive, antialias, array, drain, elephant_foot, geometry, hollow, label, lift, merge,
+    resample, resume, uniformity, zresample,
+};
+use std::path::Path;
+use std::sync::Mutex;
+
+fn check_parse_arg<T: std::str::FromStr>(input: String) -> Result<(), String>
+where
+    T::Err: std::string::ToString,
+{
+    input.parse::<T>().map(drop).map_err(|e| e.to_string())
+}
+
+fn check_printer_arg(input: String) -> Result<(), String> {
+    match Printer::by_name(&input) {
+        Some(_) => Ok(()),
+        None => Err(format!(
+            "Unknown printer, allowed values are {}",
+            Printer::names().join(", ")
+        )),
+    }
+}
+
+fn check_antialias_arg(input: String) -> Result<(), String> {
+    match input.as_ref() {
+        "1" | "2" | "4" | "8" => Ok(()),
+        _ => Err("Invalid anti-alias option, allowed values are 1, 2, 4, or 8".to_string()),
+    }
+}
+
+fn check_hole_arg(input: String) -> Result<(), String> {
+    let values: Result<Vec<f32>, _> = input.split(',').map(|v| v.parse::<f32>()).collect();
+    match values {
+        Ok(ref values) if values.len() == 4 => Ok(()),
+        _ => Err("Expected hole as x,y,bottom,top in millimeter".to_string()),
+    }
+}
+
+fn check_pair_arg(input: String) -> Result<(), String> {
+    let values: Result<Vec<f32>, _> = input.split(',').map(|v| v.parse::<f32>()).collect();
+    match values {
+        Ok(ref values) if values.len() == 2 => Ok(()),
+        _ => Err("Expected two comma separated numbers".to_string()),
+    }
+}
+
+fn check_lift_rule_arg(input: String) -> Result<(), String> {
+    let values: Result<Vec<f32>, _> = input.split(':').map(|v| v.parse::<f32>()).collect();
+    match values {
+        Ok(ref values) if values.len() == 3 => {
+            if !values.iter().all(|v| v.is_finite() && *v >= 0.0) {
+                Err("Lift rule values should be numbers of at least 0".to_string())
+            } else if values[2] == 0.0 {
+                Err("Lift speed should be positive".to_string())
+            } else {
+                Ok(())
+            }
+        }
+        _ => Err("Expected rule as area:distance:speed".to_string()),
+    }
+}
+
+fn parse_pair(input: &str) -> (f32, f32) {
+    let values: Vec<f32> = input.split(',').map(|v| v.parse().unwrap()).collect();
+    (values[0], values[1])
+}
+
+fn input_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("input")
+        .short("i")
+        .long("input")
+        .value_name("filename")
+        .help("Input .pws or .photons file")
+        .required(true)
+        .takes_value(true)
+}
+
+fn output_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .value_name("filename")
+        .help("Output .pws or .photons file")
+        .required(true)
+        .takes_value(true)
+}
+
+fn read_input(args: &ArgMatches) -> PwsFile {
+    job::read_job(Path::new(args.value_of("input").unwrap())).unwrap()
+}
+
+fn write_output(args: &ArgMatches, file: &PwsFile) {
+    job::write_job(Path::new(args.value_of("output").unwrap()), file).unwrap();
+}
+
+fn progress_bar(count: usize, message: &str) -> Mutex<ProgressBar<std::io::Stdout>> {
+    let mut pb = ProgressBar::new(count as u64);
+    pb.message(message);
+    Mutex::new(pb)
+}
+
+fn run_elephant_foot(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let settings = elephant_foot::ElephantFoot {
+        distance: args.value_of("distance").unwrap().parse().unwrap(),
+        num_layers: match args.value_of("layers") {
+            Some(layers) => layers.parse().unwrap(),
+            None => file.header.num_bottom_layers.round() as usize,
+        },
+        taper: args.is_present("taper"),
+    };
+    let pb = progress_bar(settings.num_layers, "Compensating layers: ");
+    elephant_foot::compensate_elephant_foot(&mut file, &settings, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn run_hollow(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let spacing = args.value_of("infill-spacing").unwrap().parse().unwrap();
+    let thickness = args.value_of("infill-thickness").unwrap().parse().unwrap();
+    let settings = hollow::Hollow {
+        wall_thickness: args.value_of("wall").unwrap().parse().unwrap(),
+        infill: match args.value_of("infill").unwrap() {
+            "grid" => hollow::Infill::Grid { spacing, thickness },
+            "honeycomb" => hollow::Infill::Honeycomb { spacing, thickness },
+            _ => hollow::Infill::None,
+        },
+    };
+    let pb = progress_bar(file.layers.len(), "Hollowing layers: ");
+    if let Err(e) = hollow::hollow(&mut file, &settings, |_| {
+        pb.lock().unwrap().inc();
+    }) {
+        println!("Can not hollow the part: {}", e);
+        std::process::exit(1);
+    }
+    pb.lock().unwrap().finish_print("Done");
+    println!("New volume: {:.2} mm^3", file.header.volume);
+    write_output(args, &file);
+}
+
+fn run_drain(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let diameter: f32 = args.value_of("diameter").unwrap().parse().unwrap();
+    let mut holes: Vec<drain::DrainHole> = args
+        .values_of("hole")
+        .into_iter()
+        .flatten()
+        .map(|hole| {
+            let values: Vec<f32> = hole.split(',').map(|v| v.parse().unwrap()).collect();
+            drain::DrainHole {
+                x: values[0],
+                y: values[1],
+                diameter,
+                first_layer: file.layer_at_height(values[2]),
+                last_layer: file.layer_at_height(values[3]),
+            }
+        })
+        .collect();
+    if args.is_present("auto") {
+        let pb = progress_bar(file.layers.len(), "Finding cavities: ");
+        let found = cavities::find_cavities(&file, |_| {
+            pb.lock().unwrap().inc();
+        });
+        pb.lock().unwrap().finish_print("Done");
+        holes.extend(drain::holes_for_cavities(&file, &found, diameter));
+    }
+    for hole in holes.iter() {
+        println!(
+            "Drain hole at ({:.2}, {:.2}) mm through layers {}-{}",
+            hole.x, hole.y, hole.first_layer, hole.last_layer
+        );
+    }
+    let pb = progress_bar(file.layers.len(), "Punching holes: ");
+    drain::punch_drain_holes(&mut file, &holes, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn run_geometry<F: FnOnce(&PwsFile) -> Geometry>(args: &ArgMatches, operation: F) {
+    let mut file = read_input(args);
+    let operation = operation(&file);
+    let pb = progress_bar(file.layers.len(), "Transforming layers: ");
+    let clipped = geometry::transform_geometry(&mut file, operation, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    for (index, pixels) in clipped.iter() {
+        println!(
+            "Warning: layer {} lost {} lit pixels off the edge of the plate",
+            index, pixels
+        );
+    }
+    write_output(args, &file);
+}
+
+fn run_mirror(args: &ArgMatches) {
+    let operation = match args.value_of("axis").unwrap() {
+        "x" => Geometry::MirrorX,
+        _ => Geometry::MirrorY,
+    };
+    run_geometry(args, |_| operation);
+}
+
+fn run_rotate(args: &ArgMatches) {
+    let operation = match args.value_of("angle").unwrap() {
+        "90" => Geometry::Rotate90,
+        "180" => Geometry::Rotate180,
+        _ => Geometry::Rotate270,
+    };
+    run_geometry(args, |_| operation);
+}
+
+fn run_translate(args: &ArgMatches) {
+    let x: f32 = args.value_of("x").unwrap().parse().unwrap();
+    let y: f32 = args.value_of("y").unwrap().parse().unwrap();
+    let in_mm = args.is_present("mm");
+    run_geometry(args, |file| {
+        let scale = if in_mm {
+            1.0 / file.header.pixel_size_mm()
+        } else {
+            1.0
+        };
+        Geometry::Translate((x * scale).round() as i32, (y * scale).round() as i32)
+    });
+}
+
+fn run_resample(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let printer = args.value_of("printer").and_then(Printer::by_name);
+    let width = match args.value_of("width") {
+        Some(width) => width.parse().unwrap(),
+        None => printer.map_or(file.header.width, |p| p.width),
+    };
+    let height = match args.value_of("height") {
+        Some(height) => height.parse().unwrap(),
+        None => printer.map_or(file.header.height, |p| p.height),
+    };
+    let pixel_size = match args.value_of("pixel-size") {
+        Some(pixel_size) => pixel_size.parse().unwrap(),
+        None => printer.map_or(file.header.pixel_size, |p| p.pixel_size),
+    };
+    let pb = progress_bar(file.layers.len(), "Resampling layers: ");
+    let clipped = resample::resample_job(&mut file, width, height, pixel_size, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    for (index, pixels) in clipped.iter() {
+        println!(
+            "Warning: layer {} lost {} lit pixels off the edge of the plate",
+            index, pixels
+        );
+    }
+    write_output(args, &file);
+}
+
+fn run_layer_height(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let settings = zresample::ZResample {
+        layer_height: args.value_of("height").unwrap().parse().unwrap(),
+        penetration_depth: args.value_of("penetration-depth").unwrap().parse().unwrap(),
+        interpolate: args.is_present("interpolate"),
+        antialias_levels: args.value_of("z-antialias").map(|v| v.parse().unwrap()),
+    };
+    let new_count = file.layers.len() as f32 * file.header.layer_height / settings.layer_height;
+    let pb = progress_bar(new_count.round() as usize, "Resampling layers: ");
+    if let Err(e) = zresample::resample_layer_height(&mut file, &settings, |_| {
+        pb.lock().unwrap().inc();
+    }) {
+        println!("Can not change the layer height: {}", e);
+        std::process::exit(1);
+    }
+    pb.lock().unwrap().finish_print("Done");
+    println!(
+        "{} layers, exposure {:.2} sec, bottom exposure {:.2} sec",
+        file.layers.len(),
+        file.header.exposure_time,
+        file.header.bottom_exposure_time
+    );
+    write_output(args, &file);
+}
+
+fn run_array(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let layout = array::ArrayLayout {
+        count: args.value_of("count").unwrap().parse().unwrap(),
+        columns: args.value_of("columns").map(|c| c.parse().unwrap()),
+        spacing: args.value_of("spacing").unwrap().parse().unwrap(),
+    };
+    let pb = progress_bar(file.layers.len(), "Measuring part: ");
+    let occupied = bounds::occupied_bounds(&file, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    let occupied = match occupied {
+        Some(occupied) => occupied,
+        None => {
+            println!("All layers are empty, nothing to copy");
+            return;
+        }
+    };
+    let positions = match array::plan_array(&file, &occupied, &layout) {
+        Ok(positions) => positions,
+        Err(e) => {
+            println!("Layout rejected: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let pb = progress_bar(file.layers.len(), "Copying layers: ");
+    array::array_copies(&mut file, &occupied, &positions, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn run_merge(args: &ArgMatches) {
+    let mut files: Vec<PwsFile> = args
+        .values_of("input")
+        .unwrap()
+        .map(|input| job::read_job(Path::new(input)).unwrap())
+        .collect();
+    for file in files.iter_mut() {
+        file.enable_individual_parameters();
+    }
+    if let Err(e) = merge::check_compatible(&files) {
+        println!("Jobs can not be merged: {}", e);
+        std::process::exit(1);
+    }
+    let conflicts = merge::exposure_conflicts(&files);
+    for conflict in conflicts.iter() {
+        println!("Exposure conflict: {}", conflict);
+    }
+    if !conflicts.is_empty() && !args.is_present("allow-conflicts") {
+        println!("Use --allow-conflicts to merge anyway with the settings of the first job");
+        std::process::exit(1);
+    }
+    let mut footprints = Vec::new();
+    for (index, file) in files.iter().enumerate() {
+        let pb = progress_bar(file.layers.len(), &format!("Measuring job {}: ", index));
+        let occupied = bounds::occupied_bounds(file, |_| {
+            pb.lock().unwrap().inc();
+        });
+        pb.lock().unwrap().finish_print("Done");
+        match occupied {
+            Some(occupied) => footprints.push(occupied),
+            None => {
+                println!("Job {} has no lit pixels", index);
+                std::process::exit(1);
+            }
+        }
+    }
+    let header = &files[0].header;
+    let spacing: f32 = args.value_of("spacing").unwrap().parse().unwrap();
+    let gap = (spacing / header.pixel_size_mm()).ceil() as u32;
+    let sizes: Vec<(u32, u32)> = footprints
+        .iter()
+        .map(|bounds| (bounds.width(), bounds.height()))
+        .collect();
+    let positions = match merge::pack_footprints(&sizes, (header.width, header.height), gap) {
+        Ok(positions) => positions,
+        Err(e) => {
+            println!("Layout rejected: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let num_layers = files.iter().map(|file| file.layers.len()).max().unwrap();
+    let pb = progress_bar(num_layers, "Merging layers: ");
+    let merged = merge::merge_jobs(&files, &footprints, &positions, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &merged);
+}
+
+fn run_resume(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let settings = resume::Resume {
+        start_layer: args.value_of("layer").unwrap().parse().unwrap(),
+        bottom_layers: args.value_of("bottom-layers").unwrap().parse().unwrap(),
+        bottom_exposure_time: args.value_of("bottom-exposure").map(|e| e.parse().unwrap()),
+    };
+    if let Err(e) = resume::resume_from_layer(&mut file, &settings) {
+        println!("Can not resume: {}", e);
+        std::process::exit(1);
+    }
+    println!("{} layers left to print", file.layers.len());
+    write_output(args, &file);
+}
+
+fn run_label(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let (x, y) = parse_pair(args.value_of("position").unwrap());
+    let (bottom, top) = parse_pair(args.value_of("z").unwrap());
+    if bottom > top {
+        println!("Label bottom {} mm is above its top {} mm", bottom, top);
+        std::process::exit(1);
+    }
+    let label = label::Label {
+        text: args.value_of("text").unwrap().to_string(),
+        x,
+        y,
+        height: args.value_of("size").unwrap().parse().unwrap(),
+        first_layer: file.layer_at_height(bottom),
+        last_layer: file.layer_at_height(top),
+        mode: if args.is_present("emboss") {
+            label::LabelMode::Emboss
+        } else {
+            label::LabelMode::Engrave
+        },
+        mirror: args.is_present("mirror"),
+    };
+    println!(
+        "Label of {:.2} x {:.2} mm through layers {}-{}",
+        label.width(),
+        label.height,
+        label.first_layer,
+        label.last_layer
+    );
+    let pb = progress_bar(
+        label.last_layer + 1 - label.first_layer,
+        "Stamping layers: ",
+    );
+    label::apply_label(&mut file, &label, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn read_uniformity_map(args: &ArgMatches) -> Result<uniformity::UniformityMap, String> {
+    if let Some(path) = args.value_of("map") {
+        let image = image::open(path).map_err(|e| e.to_string())?;
+        return Ok(uniformity::UniformityMap::from_image(&image.to_luma()));
+    }
+    let path = args.value_of("measurements").unwrap();
+    let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let rows: Result<Vec<Vec<f32>>, _> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split(',').map(|v| v.trim().parse::<f32>()).collect())
+        .collect();
+    uniformity::UniformityMap::from_measurements(&rows.map_err(|e| e.to_string())?)
+}
+
+fn run_uniformity(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let map = match read_uniformity_map(args) {
+        Ok(map) => map,
+        Err(e) => {
+            println!("Can not read compensation map: {}", e);
+            std::process::exit(1);
+        }
+    };
+    let output = Path::new(args.value_of("output").unwrap());
+    if job::FileFormat::from_path(output) == Some(job::FileFormat::Photons) {
+        println!("Warning: Photon S files have no grey levels, dimmed pixels will be lost");
+    }
+    let bits_per_pixel = args.value_of("antialias").unwrap().parse().unwrap();
+    let pb = progress_bar(file.layers.len(), "Compensating layers: ");
+    uniformity::compensate_uniformity(&mut file, &map, bits_per_pixel, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn run_antialias(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let method = match args.value_of("method").unwrap() {
+        "blur" => Antialias::Blur {
+            radius: args.value_of("radius").unwrap().parse().unwrap(),
+        },
+        _ => Antialias::Supersample {
+            factor: args.value_of("factor").unwrap().parse().unwrap(),
+        },
+    };
+    let bits_per_pixel = args.value_of("antialias").unwrap().parse().unwrap();
+    let pb = progress_bar(file.layers.len(), "Smoothing layers: ");
+    antialias::antialias_job(&mut file, method, bits_per_pixel, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn run_adaptive(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let settings = adaptive::AdaptiveLayers {
+        max_layer_height: args.value_of("max-height").unwrap().parse().unwrap(),
+        max_difference: args
+            .value_of("max-difference")
+            .unwrap()
+            .parse::<f32>()
+            .unwrap()
+            / 100.0,
+        penetration_depth: args.value_of("penetration-depth").unwrap().parse().unwrap(),
+    };
+    let old_count = file.layers.len();
+    let pb = progress_bar(old_count.saturating_sub(1), "Comparing layers: ");
+    let new_count = adaptive::adaptive_layer_heights(&mut file, &settings, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    println!("Merged {} layers into {}", old_count, new_count);
+    write_output(args, &file);
+}
+
+fn run_lift(args: &ArgMatches) {
+    let mut file = read_input(args);
+    let rules = lift::LiftRules {
+        metric: match args.value_of("metric").unwrap() {
+            "increase" => lift::LiftMetric::AreaIncrease,
+            _ => lift::LiftMetric::Area,
+        },
+        rules: args
+            .values_of("rule")
+            .unwrap()
+            .map(|rule| {
+                let values: Vec<f32> = rule.split(':').map(|v| v.parse().unwrap()).collect();
+                lift::LiftRule {
+                    min_area: values[0],
+                    lift_distance: values[1],
+                    lift_speed: values[2],
+                }
+            })
+            .collect(),
+    };
+    let output = Path::new(args.value_of("output").unwrap());
+    if job::FileFormat::from_path(output) == Some(job::FileFormat::Photons) {
+        println!("Warning: Photon S files have no per-layer parameters, the lift will be lost");
+    }
+    let pb = progress_bar(file.layers.len(), "Measuring layers: ");
+    lift::apply_lift_rules(&mut file, &rules, |_| {
+        pb.lock().unwrap().inc();
+    });
+    pb.lock().unwrap().finish_print("Done");
+    write_output(args, &file);
+}
+
+fn main() {
+    let args = App::new("SLA print job transformer")
+        .version(crate_version!())
+        .author("Frans-willem Hardijzer <fw@hardijzer.nl>")
+        .about("Edits the layers of sliced print jobs")
+        .subcommand(
+            SubCommand::with_name("elephant-foot")
+                .about("Shrinks the bottom layers to compensate for over-curing")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("distance")
+                        .short("d")
+                        .long("distance")
+                        .value_name("mm")
+                        .required(true)
+                        .validator(check_parse_arg::<f32>)
+                        .help("Distance to shrink the bottom layers by in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("layers")
+                        .short("n")
+                        .long("layers")
+                        .value_name("count")
+                        .validator(check_parse_arg::<usize>)
+                        .help("Number of layers to compensate, defaults to the bottom layers"),
+                )
+                .arg(
+                    Arg::with_name("taper")
+                        .long("taper")
+                        .help("Linearly reduce the distance to zero over the compensated layers"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("hollow")
+                .about("Removes the interior of solid parts, keeping walls of a given thickness")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("wall")
+                        .short("w")
+                        .long("wall")
+                        .value_name("mm")
+                        .required(true)
+                        .validator(check_parse_arg::<f32>)
+                        .help("Wall thickness in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("infill")
+                        .long("infill")
+                        .value_name("pattern")
+                        .default_value("none")
+                        .possible_values(&["none", "grid", "honeycomb"])
+                        .help("Infill pattern to keep inside the hollowed part"),
+                )
+                .arg(
+                    Arg::with_name("infill-spacing")
+                        .long("infill-spacing")
+                        .value_name("mm")
+                        .default_value("5.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Distance between infill walls in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("infill-thickness")
+                        .long("infill-thickness")
+                        .value_name("mm")
+                        .default_value("0.8")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Thickness of infill walls in millimeter"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("drain")
+                .about("Cuts drain holes into the part")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("diameter")
+                        .short("d")
+                        .long("diameter")
+                        .value_name("mm")
+                        .default_value("2.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Hole diameter in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("hole")
+                        .long("hole")
+                        .value_name("x,y,bottom,top")
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(check_hole_arg)
+                        .help("Position on the plate and height range of a hole, in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("auto")
+                        .long("auto")
+                        .help("Add a hole at the lowest point of every enclosed cavity"),
+                )
+                .group(
+                    ArgGroup::with_name("holes")
+                        .args(&["hole", "auto"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("mirror")
+                .about("Mirrors all layers and the preview")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("axis")
+                        .long("axis")
+                        .value_name("axis")
+                        .required(true)
+                        .possible_values(&["x", "y"])
+                        .help("Axis to mirror along"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rotate")
+                .about("Rotates all layers and the preview clockwise")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("angle")
+                        .long("angle")
+                        .value_name("degrees")
+                        .required(true)
+                        .possible_values(&["90", "180", "270"])
+                        .help("Clockwise rotation"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("translate")
+                .about("Moves all layers and the preview, warning about clipped pixels")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("x")
+                        .short("x")
+                        .value_name("distance")
+                        .default_value("0")
+                        .allow_hyphen_values(true)
+                        .validator(check_parse_arg::<f32>)
+                        .help("Distance to move right, in pixels unless --mm is given"),
+                )
+                .arg(
+                    Arg::with_name("y")
+                        .short("y")
+                        .value_name("distance")
+                        .default_value("0")
+                        .allow_hyphen_values(true)
+                        .validator(check_parse_arg::<f32>)
+                        .help("Distance to move down, in pixels unless --mm is given"),
+                )
+                .arg(
+                    Arg::with_name("mm")
+                        .long("mm")
+                        .help("Distances are in millimeter instead of pixels"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("resample")
+                .about("Resamples layers for a printer with a different resolution or pixel size")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("printer")
+                        .short("p")
+                        .long("printer")
+                        .value_name("name")
+                        .validator(check_printer_arg)
+                        .help("Target printer"),
+                )
+                .arg(
+                    Arg::with_name("width")
+                        .long("width")
+                        .value_name("pixels")
+                        .validator(check_parse_arg::<u32>)
+                        .help("Target width, overrides the printer"),
+                )
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .value_name("pixels")
+                        .validator(check_parse_arg::<u32>)
+                        .help("Target height, overrides the printer"),
+                )
+                .arg(
+                    Arg::with_name("pixel-size")
+                        .long("pixel-size")
+                        .value_name("um")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Target pixel size in micrometer, overrides the printer"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("layer-height")
+                .about("Merges or splits layers to print at a different layer height")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("height")
+                        .long("height")
+                        .value_name("mm")
+                        .required(true)
+                        .validator(check_parse_arg::<f32>)
+                        .help("New layer height in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("penetration-depth")
+                        .long("penetration-depth")
+                        .value_name("mm")
+                        .default_value("0.15")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Resin penetration depth in millimeter, used to rescale exposure"),
+                )
+                .arg(
+                    Arg::with_name("interpolate")
+                        .long("interpolate")
+                        .help("Morph between layers when splitting, instead of repeating them"),
+                )
+                .arg(
+                    Arg::with_name("z-antialias")
+                        .long("z-antialias")
+                        .value_name("levels")
+                        .validator(check_antialias_arg)
+                        .help("Encode partially filled pixels as grey, with this many levels"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("array")
+                .about("Fills the plate with a grid of copies of the part")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("count")
+                        .short("n")
+                        .long("count")
+                        .value_name("copies")
+                        .required(true)
+                        .validator(check_parse_arg::<usize>)
+                        .help("Total number of copies"),
+                )
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .value_name("count")
+                        .validator(check_parse_arg::<usize>)
+                        .help("Copies per row, defaults to a roughly square grid"),
+                )
+                .arg(
+                    Arg::with_name("spacing")
+                        .short("s")
+                        .long("spacing")
+                        .value_name("mm")
+                        .default_value("2.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Gap between copies in millimeter"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("merge")
+                .about("Packs several jobs together onto the plate of the first")
+                .arg(input_arg().multiple(true).number_of_values(1))
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("spacing")
+                        .short("s")
+                        .long("spacing")
+                        .value_name("mm")
+                        .default_value("2.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Gap between jobs in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("allow-conflicts")
+                        .long("allow-conflicts")
+                        .help(
+                            "Merge jobs with different exposure settings, using those of the first",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("resume")
+                .about("Keeps only the layers from the given layer on, to continue a failed print")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("layer")
+                        .short("l")
+                        .long("layer")
+                        .value_name("index")
+                        .required(true)
+                        .validator(check_parse_arg::<usize>)
+                        .help("First layer to print, counting from 0"),
+                )
+                .arg(
+                    Arg::with_name("bottom-layers")
+                        .long("bottom-layers")
+                        .value_name("count")
+                        .default_value("2")
+                        .validator(check_parse_arg::<usize>)
+                        .help("Number of new layers to expose as bottom layers"),
+                )
+                .arg(
+                    Arg::with_name("bottom-exposure")
+                        .long("bottom-exposure")
+                        .value_name("seconds")
+                        .validator(check_parse_arg::<f32>)
+                        .help(
+                            "Exposure of the bottom layers, defaults to that of the original job",
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("label")
+                .about("Engraves or embosses a line of text into a range of layers")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("text")
+                        .short("t")
+                        .long("text")
+                        .value_name("text")
+                        .required(true)
+                        .help("Text to stamp, digits, letters and some punctuation"),
+                )
+                .arg(
+                    Arg::with_name("position")
+                        .short("p")
+                        .long("position")
+                        .value_name("x,y")
+                        .required(true)
+                        .validator(check_pair_arg)
+                        .help("Top-left corner of the text in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .short("s")
+                        .long("size")
+                        .value_name("mm")
+                        .default_value("3.0")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Height of the letters"),
+                )
+                .arg(
+                    Arg::with_name("z")
+                        .short("z")
+                        .long("z")
+                        .value_name("bottom,top")
+                        .default_value("0,0.5")
+                        .validator(check_pair_arg)
+                        .help("Height range of the text in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("emboss")
+                        .long("emboss")
+                        .help("Add the text to the part instead of cutting it out"),
+                )
+                .arg(
+                    Arg::with_name("mirror")
+                        .long("mirror")
+                        .help("Mirror the text, to read it from the bottom of the part"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("uniformity")
+                .about("Dims every layer where the LCD backlight is brighter than elsewhere")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("map")
+                        .short("m")
+                        .long("map")
+                        .value_name("filename")
+                        .help("Greyscale image stretched over the plate, white keeps full power"),
+                )
+                .arg(
+                    Arg::with_name("measurements")
+                        .long("measurements")
+                        .value_name("filename")
+                        .help("CSV file with a grid of UV meter readings over the plate"),
+                )
+                .group(
+                    ArgGroup::with_name("compensation")
+                        .args(&["map", "measurements"])
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("antialias")
+                        .short("a")
+                        .long("antialias")
+                        .value_name("levels")
+                        .default_value("4")
+                        .validator(check_antialias_arg)
+                        .help("Minimum number of anti-aliasing levels to encode the layers with"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("antialias")
+                .about("Adds anti-aliasing to layers that are pure black and white")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("method")
+                        .short("m")
+                        .long("method")
+                        .value_name("method")
+                        .possible_values(&["blur", "supersample"])
+                        .default_value("supersample"),
+                )
+                .arg(
+                    Arg::with_name("radius")
+                        .long("radius")
+                        .value_name("pixels")
+                        .default_value("1")
+                        .validator(check_parse_arg::<u32>)
+                        .help("Blur radius"),
+                )
+                .arg(
+                    Arg::with_name("factor")
+                        .long("factor")
+                        .value_name("count")
+                        .default_value("4")
+                        .validator(check_parse_arg::<u32>)
+                        .help("Samples per pixel along each axis when supersampling"),
+                )
+                .arg(
+                    Arg::with_name("antialias")
+                        .short("a")
+                        .long("antialias")
+                        .value_name("levels")
+                        .default_value("4")
+                        .validator(check_antialias_arg)
+                        .help("Minimum number of anti-aliasing levels to encode the layers with"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("adaptive")
+                .about("Merges consecutive similar layers into thicker ones")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("max-height")
+                        .long("max-height")
+                        .value_name("mm")
+                        .default_value("0.1")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Thickest layer to create in millimeter"),
+                )
+                .arg(
+                    Arg::with_name("max-difference")
+                        .long("max-difference")
+                        .value_name("percent")
+                        .default_value("2")
+                        .validator(check_parse_arg::<f32>)
+                        .help("How much of the cross-section may change within a merged layer"),
+                )
+                .arg(
+                    Arg::with_name("penetration-depth")
+                        .long("penetration-depth")
+                        .value_name("mm")
+                        .default_value("0.15")
+                        .validator(check_parse_arg::<f32>)
+                        .help("Resin penetration depth in millimeter, used to rescale exposure"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("lift")
+                .about("Sets the lift of every layer from its lit area")
+                .arg(input_arg())
+                .arg(output_arg())
+                .arg(
+                    Arg::with_name("rule")
+                        .short("r")
+                        .long("rule")
+                        .value_name("area:distance:speed")
+                        .required(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .validator(check_lift_rule_arg)
+                        .help("Lift in mm and mm/sec for layers from this area in mm^2 up"),
+                )
+                .arg(
+                    Arg::with_name("metric")
+                        .short("m")
+                        .long("metric")
+                        .value_name("metric")
+                        .possible_values(&["area", "increase"])
+                        .default_value("area")
+                        .help("Compare the layer area, or the area added to the layer below"),
+                ),
+        )
+        .get_matches();
+
+    match args.subcommand() {
+        ("elephant-foot", Some(sub_args)) => run_elephant_foot(sub_args),
+        ("hollow", Some(sub_args)) => run_hollow(sub_args),
+        ("drain", Some(sub_args)) => run_drain(sub_args),
+        ("mirror", Some(sub_args)) => run_mirror(sub_args),
+        ("rotate", Some(sub_args)) => run_rotate(sub_args),
+        ("translate", Some(sub_args)) => run_translate(sub_args),
+        ("resample", Some(sub_args)) => run_resample(sub_args),
+        ("layer-height", Some(sub_args)) => run_layer_height(sub_args),
+        ("array", Some(sub_args)) => run_array(sub_args),
+        ("merge", Some(sub_args)) => run_merge(sub_args),
+        ("resume", Some(sub_args)) => run_resume(sub_args),
+        ("label", Some(sub_args)) => run_label(sub_args),
+        ("uniformity", Some(sub_args)) => run_uniformity(sub_args),
+        ("antialias", Some(sub_args)) => run_antialias(sub_args),
+        ("adaptive", Some(sub_args)) => run_adaptive(sub_args),
+        ("lift", Some(sub_args)) => run_lift(sub_args),
+        _ => println!("{}", args.usage()),
+    }
+}
@@ -0,0 +1,161 @@
+use crate::formats::photons;
+use crate::formats::pws;
+use std::fs::File;
+use std::io::{BufWriter, Error, ErrorKind, Read};
+use std::path::Path;
+
+/*
+ * All tools work on PwsFile as their in-memory representation, as it is the superset of what the
+ * supported formats can express (anti-aliasing, per-layer parameters). Other formats are
+ * converted to and from it when reading or writing.
+ */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum FileFormat {
+    Pws,
+    Photons,
+}
+
+impl FileFormat {
+    pub fn from_path(path: &Path) -> Option<FileFormat> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_ref() {
+            "pws" => Some(FileFormat::Pws),
+            "photons" => Some(FileFormat::Photons),
+            _ => None,
+        }
+    }
+}
+
+fn invalid_data<E: std::fmt::Debug>(e: E) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("{:?}", e))
+}
+
+fn unknown_format(path: &Path) -> Error {
+    Error::new(
+        ErrorKind::InvalidInput,
+        format!("Unknown file format: {}", path.display()),
+    )
+}
+
+pub fn photons_to_pws(file: &photons::data::PhotonsFile) -> pws::data::PwsFile {
+    let (width, height) = file
+        .layers
+        .first()
+        .map(|layer| (layer.width, layer.height))
+        .unwrap_or((0, 0));
+    let header = pws::data::PwsHeader {
+        pixel_size: (file.pixelsize * 1000.0) as f32,
+        layer_height: file.layerheight as f32,
+        exposure_time: file.exposure_time as f32,
+        off_time: file.off_time as f32,
+        bottom_exposure_time: file.bottom_exposure_time as f32,
+        num_bottom_layers: file.num_bottom_layers as f32,
+        lift_distance: file.lift_distance as f32,
+        lift_speed: file.lift_speed as f32,
+        drop_speed: file.retract_speed as f32,
+        volume: file.total_volume as f32,
+        bits_per_pixel: 1,
+        width,
+        height,
+        weight: 0.0,
+        price: 0.0,
+        resin_type: 0,
+        use_individual_parameters: false,
+    };
+    let layers = file
+        .layers
+        .iter()
+        .enumerate()
+        .map(|(index, layer)| {
+            let bits = layer.data.decompress((layer.width * layer.height) as usize);
+            pws::data::PwsLayer {
+                lift_distance: header.lift_distance,
+                lift_speed: header.lift_speed,
+                exposure_time: if index < file.num_bottom_layers as usize {
+                    header.bottom_exposure_time
+                } else {
+                    header.exposure_time
+                },
+                layer_height: header.layer_height,
+                data: pws::data::CompressedBitstream::compress(bits.into_iter()),
+            }
+        })
+        .collect();
+    pws::data::PwsFile {
+        header,
+        preview: file.thumbnail.clone(),
+        layers,
+    }
+}
+
+/// Photon S layers are single bit, so anti-aliased pixels are thresholded at half intensity.
+/// Per-layer parameters are lost.
+pub fn pws_to_photons(file: &pws::data::PwsFile) -> photons::data::PhotonsFile {
+    let header = &file.header;
+    let layers = (0..file.layers.len())
+        .map(|index| {
+            let image = file.decode_layer(index);
+            let bits: Vec<bool> = image.pixels().map(|p| p.0[0] >= 128).collect();
+            photons::data::PhotonsLayer {
+                width: header.width,
+                height: header.height,
+                data: photons::data::CompressedBitstream::compress(&bits),
+            }
+        })
+        .collect();
+    photons::data::PhotonsFile {
+        pixelsize: header.pixel_size_mm() as f64,
+        layerheight: header.layer_height as f64,
+        exposure_time: header.exposure_time as f64,
+        off_time: header.off_time as f64,
+        bottom_exposure_time: header.bottom_exposure_time as f64,
+        num_bottom_layers: header.num_bottom_layers.round() as u32,
+        lift_distance: header.lift_distance as f64,
+        lift_speed: header.lift_speed as f64,
+        retract_speed: header.drop_speed as f64,
+        total_volume: header.volume as f64,
+        thumbnail: file.preview.clone(),
+        layers,
+    }
+}
+
+/// Reads a job without checking that its layers can be decoded, for tools that report on broken
+/// files.
+pub fn read_job_unchecked(path: &Path) -> std::io::Result<pws::data::PwsFile> {
+    let format = FileFormat::from_path(path).ok_or_else(|| unknown_format(path))?;
+    let mut input = Vec::new();
+    File::open(path)?.read_to_end(&mut input)?;
+    match format {
+        FileFormat::Pws => pws::parse::parse_pws_file(&input)
+            .map(|(_, file)| file)
+            .map_err(invalid_data),
+        FileFormat::Photons => photons::parse::parse_photons_file(&input)
+            .map(|(_, file)| photons_to_pws(&file))
+            .map_err(invalid_data),
+    }
+}
+
+/// Reads a job and checks that all of its layers can be decoded.
+pub fn read_job(path: &Path) -> std::io::Result<pws::data::PwsFile> {
+    let file = read_job_unchecked(path)?;
+    file.check_layers()
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))?;
+    Ok(file)
+}
+
+pub fn write_job(path: &Path, file: &pws::data::PwsFile) -> std::io::Result<()> {
+    let format = FileFormat::from_path(path).ok_or_else(|| unknown_format(path))?;
+    let output = BufWriter::new(File::create(path)?);
+    match format {
+        FileFormat::Pws => cookie_factory::gen(pws::gen::gen_pws_file(file), output)
+            .map(drop)
+            .map_err(invalid_data),
+        FileFormat::Photons => {
+            let file = pws_to_photons(file);
+            cookie_factory::gen(photons::gen::gen_photons_file(&file), output)
+                .map(drop)
+                .map_err(invalid_data)
+        }
+    }
+}
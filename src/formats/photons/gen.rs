@@ -0,0 +1,54 @@
+use crate::formats::photons::data::*;
+use crate::gen_rgb565::gen_rgb565_image;
+use cookie_factory::bytes::*;
+use cookie_factory::combinator::*;
+use cookie_factory::multi::*;
+use cookie_factory::sequence::*;
+use cookie_factory::SerializeFn;
+use image::RgbImage;
+use std::io::Write;
+
+pub fn gen_photons_file<W: Write + 'static>(file: &PhotonsFile) -> impl SerializeFn<W> + '_ {
+    tuple((
+        be_u32(2),    // Version
+        be_u16(0x31), // Unknown
+        tuple((
+            be_f64(file.pixelsize),
+            be_f64(file.layerheight),
+            be_f64(file.exposure_time),
+            be_f64(file.off_time),
+            be_f64(file.bottom_exposure_time),
+            be_u32(file.num_bottom_layers),
+            be_f64(file.lift_distance),
+            be_f64(file.lift_speed),
+            be_f64(file.retract_speed),
+            be_f64(file.total_volume),
+        )),
+        gen_photons_thumbnail(&file.thumbnail),
+        be_u32(file.layers.len() as u32),
+        many_ref(&file.layers, gen_photons_layer),
+    ))
+}
+
+fn gen_photons_thumbnail<W: Write>(thumbnail: &RgbImage) -> impl SerializeFn<W> {
+    tuple((
+        be_u32(thumbnail.width()),
+        be_u32(42),
+        be_u32(thumbnail.height()),
+        be_u32(10),
+        gen_rgb565_image(thumbnail),
+    ))
+}
+
+fn gen_photons_layer<W: Write + 'static>(layer: &PhotonsLayer) -> impl SerializeFn<W> + '_ {
+    tuple((
+        be_u32(layer.data.num_ones as u32),
+        be_u64(0),
+        be_u32(layer.width),
+        be_u32(layer.height),
+        be_u32((layer.data.data.len() as u32 * 8) + 32),
+        le_u16((layer.width as u16).reverse_bits()),
+        le_u16((layer.height as u16).reverse_bits()),
+        slice(&layer.data.data),
+    ))
+}
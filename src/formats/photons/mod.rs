@@ -1,2 +1,3 @@
 pub mod data;
+pub mod gen;
 pub mod parse;
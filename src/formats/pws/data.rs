@@ -1,6 +1,8 @@
+use crate::formats::pws::transfer::TransferFunction;
+use crate::raster::dither::{dither, Dither};
 use image::{GrayImage, RgbImage};
 
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub struct PwsHeader {
     pub pixel_size: f32,           // in um
     pub layer_height: f32,         // in mm
@@ -90,6 +92,16 @@ impl CompressedBitstream {
         }
     }
 
+    /// Number of bits the stream decodes to, without decoding it.
+    pub fn decoded_len(&self) -> usize {
+        self.0.iter().map(|v| (*v & 0x7F) as usize).sum()
+    }
+
+    /// True if no bit of the stream is set, i.e. the layer is completely black.
+    pub fn is_blank(&self) -> bool {
+        self.0.iter().all(|v| (*v & 0x80) == 0 || (*v & 0x7F) == 0)
+    }
+
     pub fn to_image(&self, width: u32, height: u32) -> Option<GrayImage> {
         let decoded = self.decompress();
         let buffer_size = (width * height) as usize;
@@ -122,6 +134,33 @@ impl CompressedBitstream {
         compressed
     }
 
+    /// Encodes the image after remapping its grey values, optionally dithering them to the
+    /// levels of `bits_per_pixel` instead of rounding.
+    pub fn from_image_with(
+        image: &GrayImage,
+        bits_per_pixel: usize,
+        transfer: &TransferFunction,
+        dither_method: Option<Dither>,
+    ) -> CompressedBitstream {
+        let mapped = transfer.apply(image);
+        let mapped = match dither_method {
+            Some(method) => dither(&mapped, bits_per_pixel, method),
+            None => mapped,
+        };
+        CompressedBitstream::from_image(&mapped, bits_per_pixel)
+    }
+
+    /// Decodes the image, mapping grey values back through the inverse of `transfer`.
+    pub fn to_image_with(
+        &self,
+        width: u32,
+        height: u32,
+        transfer: &TransferFunction,
+    ) -> Option<GrayImage> {
+        self.to_image(width, height)
+            .map(|image| transfer.invert(&image))
+    }
+
     pub fn debug_out<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
         let mut offset: usize = 0;
         for b in self.0.iter() {
@@ -134,6 +173,92 @@ impl CompressedBitstream {
     }
 }
 
+/// Smallest anti-aliasing depth printers read, of 1, 2, 4 or 8 bits, that has at least
+/// `bits_per_pixel`. Transforms that add grey levels round the depth they are asked for up to this.
+pub fn supported_bits_per_pixel(bits_per_pixel: u32) -> u32 {
+    bits_per_pixel.next_power_of_two().clamp(1, 8)
+}
+
+impl PwsHeader {
+    pub fn pixel_size_mm(&self) -> f32 {
+        self.pixel_size / 1000.0
+    }
+}
+
+impl PwsFile {
+    /// Thickness of a single layer in mm, honouring per-layer parameters when enabled.
+    pub fn layer_thickness(&self, index: usize) -> f32 {
+        if self.header.use_individual_parameters {
+            self.layers[index].layer_height
+        } else {
+            self.header.layer_height
+        }
+    }
+
+    /// Enables per-layer parameters. Per-layer values may not have been maintained while they
+    /// were unused, so they are first set to the header values the printer used instead.
+    pub fn enable_individual_parameters(&mut self) {
+        if self.header.use_individual_parameters {
+            return;
+        }
+        let header = &self.header;
+        for (index, layer) in self.layers.iter_mut().enumerate() {
+            layer.layer_height = header.layer_height;
+            layer.exposure_time = if (index as f32) < header.num_bottom_layers {
+                header.bottom_exposure_time
+            } else {
+                header.exposure_time
+            };
+            layer.lift_distance = header.lift_distance;
+            layer.lift_speed = header.lift_speed;
+        }
+        self.header.use_individual_parameters = true;
+    }
+
+    /// Index of the layer that contains height `z` in mm, clamped to the last layer.
+    pub fn layer_at_height(&self, z: f32) -> usize {
+        let mut top = 0.0;
+        for index in 0..self.layers.len() {
+            top += self.layer_thickness(index);
+            if z < top {
+                return index;
+            }
+        }
+        self.layers.len().saturating_sub(1)
+    }
+
+    pub fn layer_image(&self, index: usize) -> Option<GrayImage> {
+        self.layers[index]
+            .data
+            .to_image(self.header.width, self.header.height)
+    }
+
+    /// Decodes a layer, for jobs whose layers `check_layers` accepted, as `job::read_job` does.
+    pub fn decode_layer(&self, index: usize) -> GrayImage {
+        self.layer_image(index).unwrap_or_else(|| {
+            panic!(
+                "Layer {} does not decode to {} x {} images",
+                index, self.header.width, self.header.height
+            )
+        })
+    }
+
+    /// Checks that every layer decodes to whole images of the size in the header.
+    pub fn check_layers(&self) -> Result<(), String> {
+        let image_size = self.header.width as usize * self.header.height as usize;
+        for (index, layer) in self.layers.iter().enumerate() {
+            let decoded_len = layer.data.decoded_len();
+            if image_size == 0 || decoded_len == 0 || !decoded_len.is_multiple_of(image_size) {
+                return Err(format!(
+                    "Layer {} does not decode to {} x {} images",
+                    index, self.header.width, self.header.height
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
 #[test]
 fn test_decompress_compress() {
     // These tests check if the last byte is allowed to repeat 126 times, whereas all others only
@@ -145,3 +270,24 @@ fn test_decompress_compress() {
     let recompressed = CompressedBitstream::compress(input.decompress().into_iter());
     assert_eq!(input, recompressed);
 }
+
+#[test]
+fn test_transfer_round_trip() {
+    let image = GrayImage::from_raw(4, 1, vec![0, 100, 200, 255]).unwrap();
+    let gamma = TransferFunction::gamma(2.2, 0, 255);
+    let encoded = CompressedBitstream::from_image_with(&image, 8, &gamma, None);
+    // Mid greys are encoded darker, and decoding brings them back up to near where they were
+    assert_eq!(
+        encoded.to_image(4, 1).unwrap().into_raw(),
+        vec![0, 31, 159, 255]
+    );
+    let decoded = encoded.to_image_with(4, 1, &gamma).unwrap();
+    for (original, decoded) in image.pixels().zip(decoded.pixels()) {
+        assert!((original.0[0] as i32 - decoded.0[0] as i32).abs() <= 8);
+    }
+    // Re-encoding the decoded image gives the same layer
+    assert_eq!(
+        CompressedBitstream::from_image_with(&decoded, 8, &gamma, None),
+        encoded
+    );
+}
@@ -1,10 +1,11 @@
 use crate::formats::pws::data::*;
+use crate::gen_rgb565::gen_rgb565_image;
 use cookie_factory::bytes::*;
 use cookie_factory::combinator::*;
 use cookie_factory::multi::*;
 use cookie_factory::sequence::*;
 use cookie_factory::SerializeFn;
-use image::{Pixel, Rgb, RgbImage};
+use image::RgbImage;
 use std::io::Write;
 
 const PWS_FILE_HEADER_SIZE: u32 = 0x30;
@@ -69,27 +70,18 @@ fn gen_pws_header<W: Write>(header: &PwsHeader) -> impl SerializeFn<W> {
     ))
 }
 
-fn encode_rgb565(pixel: &Rgb<u8>) -> u16 {
-    let data = pixel.channels();
-    let r = (data[0] >> 3) as u16;
-    let g = (data[1] >> 2) as u16;
-    let b = (data[2] >> 3) as u16;
-    (b << 11) | (g << 5) | r
-}
-
 fn calc_pws_preview_size(preview: &RgbImage) -> u32 {
     16 + 12 + (preview.width() * preview.height() * 2)
 }
 
 fn gen_pws_preview<W: Write>(preview: &RgbImage) -> impl SerializeFn<W> {
-    let gen_pixels: Vec<_> = preview.pixels().map(encode_rgb565).collect();
     tuple((
         slice(&b"PREVIEW\0\0\0\0\0"[..]),
         le_u32((preview.width() * preview.height() * 2) + 12),
         le_u32(preview.width()),
         slice(&b"*\0\0\0"[..]),
         le_u32(preview.height()),
-        many_ref(gen_pixels, le_u16),
+        gen_rgb565_image(preview),
     ))
 }
 
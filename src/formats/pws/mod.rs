@@ -1,3 +1,4 @@
 pub mod data;
 pub mod gen;
 pub mod parse;
+pub mod transfer;
@@ -0,0 +1,121 @@
+use image::GrayImage;
+
+/*
+ * Anti-aliasing levels are linear in the grey value of the source, but resin cure is not. A
+ * transfer function remaps grey values before they are split into bit planes. It is kept as a
+ * lookup table, which should be non-decreasing for the inverse to make sense.
+ */
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TransferFunction {
+    pub lut: Vec<u8>, // 256 entries
+}
+
+impl Default for TransferFunction {
+    fn default() -> TransferFunction {
+        TransferFunction {
+            lut: (0..=255).collect(),
+        }
+    }
+}
+
+impl TransferFunction {
+    /// Power curve, with lit pixels scaled into `min`..=`max`. Black stays black.
+    pub fn gamma(gamma: f32, min: u8, max: u8) -> TransferFunction {
+        let lut = (0..=255u32)
+            .map(|value| {
+                if value == 0 {
+                    return 0;
+                }
+                let curved = (value as f32 / 255.0).powf(gamma);
+                (min as f32 + curved * (max as f32 - min as f32)).round() as u8
+            })
+            .collect();
+        TransferFunction { lut }
+    }
+
+    /// Piecewise linear curve through `input,output` points, one per line. Values beyond the
+    /// first and last points are held. Empty lines and lines starting with # are skipped.
+    pub fn from_csv(contents: &str) -> Result<TransferFunction, String> {
+        let mut points = Vec::new();
+        for line in contents.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let values: Result<Vec<u8>, _> = line.split(',').map(|v| v.trim().parse()).collect();
+            match values {
+                Ok(ref values) if values.len() == 2 => points.push((values[0], values[1])),
+                _ => return Err(format!("Expected input,output from 0 to 255: {}", line)),
+            }
+        }
+        if points.is_empty() {
+            return Err("No points in transfer function".to_string());
+        }
+        points.sort();
+        let lut = (0..=255u8)
+            .map(|value| {
+                let next = points.iter().position(|point| point.0 >= value);
+                match next {
+                    None => points[points.len() - 1].1,
+                    Some(0) => points[0].1,
+                    Some(next) => {
+                        let (x0, y0) = points[next - 1];
+                        let (x1, y1) = points[next];
+                        let t = (value - x0) as f32 / (x1 - x0) as f32;
+                        (y0 as f32 + t * (y1 as f32 - y0 as f32)).round() as u8
+                    }
+                }
+            })
+            .collect();
+        Ok(TransferFunction { lut })
+    }
+
+    pub fn apply(&self, image: &GrayImage) -> GrayImage {
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            pixel.0[0] = self.lut[pixel.0[0] as usize];
+        }
+        output
+    }
+
+    /// Maps every value back to the lowest grey value that reaches it.
+    pub fn invert(&self, image: &GrayImage) -> GrayImage {
+        let inverse: Vec<u8> = (0..=255u8)
+            .map(|value| {
+                self.lut
+                    .iter()
+                    .position(|mapped| *mapped >= value)
+                    .unwrap_or(255) as u8
+            })
+            .collect();
+        let mut output = image.clone();
+        for pixel in output.pixels_mut() {
+            pixel.0[0] = inverse[pixel.0[0] as usize];
+        }
+        output
+    }
+}
+
+#[test]
+fn test_transfer_function() {
+    let image = GrayImage::from_raw(4, 1, vec![0, 1, 128, 255]).unwrap();
+    let identity = TransferFunction::default();
+    assert_eq!(
+        identity.invert(&identity.apply(&image)).into_raw(),
+        vec![0, 1, 128, 255]
+    );
+
+    let clamped = TransferFunction::gamma(1.0, 100, 200);
+    assert_eq!(clamped.apply(&image).into_raw(), vec![0, 100, 150, 200]);
+
+    let curve = TransferFunction::from_csv("# input,output\n0,0\n100,200\n200,255\n").unwrap();
+    let image = GrayImage::from_raw(4, 1, vec![0, 1, 150, 255]).unwrap();
+    assert_eq!(curve.apply(&image).into_raw(), vec![0, 2, 228, 255]);
+    // Everything from 200 up maps to 255, so 255 maps back to 200
+    assert_eq!(
+        curve.invert(&curve.apply(&image)).into_raw(),
+        vec![0, 1, 150, 200]
+    );
+    assert!(TransferFunction::from_csv("1;2").is_err());
+}
@@ -0,0 +1,18 @@
+use cookie_factory::bytes::le_u16;
+use cookie_factory::multi::many_ref;
+use cookie_factory::SerializeFn;
+use image::{Pixel, Rgb, RgbImage};
+use std::io::Write;
+
+pub fn encode_rgb565(pixel: &Rgb<u8>) -> u16 {
+    let data = pixel.channels();
+    let r = (data[0] >> 3) as u16;
+    let g = (data[1] >> 2) as u16;
+    let b = (data[2] >> 3) as u16;
+    (b << 11) | (g << 5) | r
+}
+
+pub fn gen_rgb565_image<W: Write>(image: &RgbImage) -> impl SerializeFn<W> {
+    let gen_pixels: Vec<_> = image.pixels().map(encode_rgb565).collect();
+    many_ref(gen_pixels, le_u16)
+}
@@ -0,0 +1,268 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{PwsFile, PwsLayer};
+use crate::generate::{new_job, new_layer, PrintSettings};
+use crate::printer::Printer;
+use crate::raster::font::{render_text, GLYPH_HEIGHT};
+use crate::raster::Bitmap;
+use image::{GrayImage, Luma};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Every exposure time gets its own cell of test features on the plate. Within each layer the
+ * cells are exposed together for the shortest time, after which zero-height layers keep
+ * exposing the cells that need more, blanking the others. Cells are sorted by exposure, so every
+ * sub-exposure lights a suffix of them. Only formats with per-layer parameters can do this.
+ *
+ * Cell layout in mm, from the top-left corner of the cell:
+ * - a solid base plate covering the whole cell,
+ * - a row of pins of increasing diameter,
+ * - a block with a row of holes of increasing diameter,
+ * - a bridge spanning between two pillars,
+ * - the exposure time as embossed text.
+ */
+
+const CELL_SIZE: f32 = 14.0;
+const CELL_GAP: f32 = 2.0;
+const FEATURE_DIAMETERS: [f32; 5] = [0.4, 0.6, 0.8, 1.0, 1.2];
+const PIN_ROW: f32 = 2.5;
+const HOLE_BLOCK: (f32, f32) = (4.5, 7.5);
+const BRIDGE: (f32, f32) = (8.5, 10.0);
+const BRIDGE_THICKNESS: f32 = 0.5;
+const LABEL: (f32, f32) = (11.0, 13.0);
+const LABEL_HEIGHT: f32 = 0.6;
+const MARGIN: f32 = 1.0;
+const MAX_CELLS: usize = 255; // Cells are numbered in a greyscale image
+
+#[derive(Clone, Debug)]
+pub struct Calibration {
+    pub exposure_times: Vec<f32>, // One cell per exposure time, in sec
+    pub base_height: f32,         // in mm
+    pub feature_height: f32,      // Height of features above the base, in mm
+}
+
+fn feature_x(index: usize) -> f32 {
+    2.0 + index as f32 * 2.5
+}
+
+fn in_circle(x: f32, y: f32, cx: f32, cy: f32, diameter: f32) -> bool {
+    (x - cx).powi(2) + (y - cy).powi(2) <= (diameter / 2.0).powi(2)
+}
+
+/// Whether the point (x, y, z) in mm relative to the cell corner is part of the cell.
+fn cell_contains(settings: &Calibration, label: &Bitmap, x: f32, y: f32, z: f32) -> bool {
+    let top = settings.base_height + settings.feature_height;
+    if z < settings.base_height {
+        return true;
+    }
+    if z >= top {
+        return false;
+    }
+    let pins = FEATURE_DIAMETERS
+        .iter()
+        .enumerate()
+        .any(|(index, diameter)| in_circle(x, y, feature_x(index), PIN_ROW, *diameter));
+    let inner = (MARGIN..CELL_SIZE - MARGIN).contains(&x);
+    let in_block = inner
+        && (HOLE_BLOCK.0..HOLE_BLOCK.1).contains(&y)
+        && !FEATURE_DIAMETERS
+            .iter()
+            .enumerate()
+            .any(|(index, diameter)| {
+                in_circle(
+                    x,
+                    y,
+                    feature_x(index),
+                    (HOLE_BLOCK.0 + HOLE_BLOCK.1) / 2.0,
+                    *diameter,
+                )
+            });
+    let in_bridge_row = (BRIDGE.0..BRIDGE.1).contains(&y);
+    let pillars = in_bridge_row
+        && ((MARGIN..MARGIN + 1.5).contains(&x)
+            || (CELL_SIZE - MARGIN - 1.5..CELL_SIZE - MARGIN).contains(&x));
+    let beam = in_bridge_row && inner && z >= (top - BRIDGE_THICKNESS).max(settings.base_height);
+    let text = z < settings.base_height + LABEL_HEIGHT && (LABEL.0..LABEL.1).contains(&y) && {
+        let module = f32::min(
+            (LABEL.1 - LABEL.0) / GLYPH_HEIGHT as f32,
+            (CELL_SIZE - 2.0 * MARGIN) / label.width as f32,
+        );
+        let (lx, ly) = (((x - MARGIN) / module), ((y - LABEL.0) / module));
+        lx >= 0.0
+            && (lx as u32) < label.width
+            && (ly as u32) < label.height
+            && label.get(lx as u32, ly as u32)
+    };
+    pins || in_block || pillars || beam || text
+}
+
+/// Builds the calibration job. Cells are laid out in a grid in the centre of the plate.
+pub fn generate_calibration<F>(
+    printer: &Printer,
+    print: &PrintSettings,
+    settings: &Calibration,
+    on_layer: F,
+) -> Result<PwsFile, String>
+where
+    F: Fn(usize) + Sync,
+{
+    let mut exposure_times = settings.exposure_times.clone();
+    exposure_times.sort_by(f32::total_cmp);
+    let count = exposure_times.len();
+    if count == 0 {
+        return Err("Need at least one exposure time".to_string());
+    }
+    if count > MAX_CELLS {
+        return Err(format!(
+            "At most {} exposure times are supported",
+            MAX_CELLS
+        ));
+    }
+    // Sorted, so only the shortest and longest need checking
+    if !(exposure_times[0] > 0.0 && exposure_times[count - 1].is_finite()) {
+        return Err("Exposure times must be positive".to_string());
+    }
+    if !(print.layer_height > 0.0 && print.layer_height.is_finite()) {
+        return Err(format!(
+            "Layer height of {} mm should be positive",
+            print.layer_height
+        ));
+    }
+    let columns = (count as f32).sqrt().ceil() as usize;
+    let rows = count.div_ceil(columns);
+    let pixel_size = printer.pixel_size_mm();
+    let grid_width = columns as f32 * (CELL_SIZE + CELL_GAP) - CELL_GAP;
+    let grid_height = rows as f32 * (CELL_SIZE + CELL_GAP) - CELL_GAP;
+    let (plate_width, plate_height) = (
+        printer.width as f32 * pixel_size,
+        printer.height as f32 * pixel_size,
+    );
+    if grid_width > plate_width || grid_height > plate_height {
+        return Err(format!(
+            "{} cells need {:.1} x {:.1} mm, but the plate is only {:.1} x {:.1} mm",
+            count, grid_width, grid_height, plate_width, plate_height
+        ));
+    }
+    let left = (plate_width - grid_width) / 2.0;
+    let top = (plate_height - grid_height) / 2.0;
+    let labels: Vec<Bitmap> = exposure_times
+        .iter()
+        .map(|time| render_text(&format!("{}", time)))
+        .collect();
+
+    let mut file = new_job(
+        printer,
+        &PrintSettings {
+            exposure_time: exposure_times[0],
+            ..*print
+        },
+        1,
+    );
+    let num_layers = ((settings.base_height + settings.feature_height) / print.layer_height)
+        .ceil()
+        .max(1.0) as usize;
+    let file_ref = &file;
+    let layers: Vec<Vec<PwsLayer>> = (0..num_layers)
+        .into_par_iter()
+        .map(|index| {
+            let z = (index as f32 + 0.5) * print.layer_height;
+            // Every lit pixel holds the number of the cell it belongs to, counting from 1
+            let cells = GrayImage::from_fn(printer.width, printer.height, |x, y| {
+                let (x, y) = ((x as f32 + 0.5) * pixel_size, (y as f32 + 0.5) * pixel_size);
+                let (column, row) = (
+                    ((x - left) / (CELL_SIZE + CELL_GAP)).floor(),
+                    ((y - top) / (CELL_SIZE + CELL_GAP)).floor(),
+                );
+                if column < 0.0 || row < 0.0 || column >= columns as f32 {
+                    return Luma([0]);
+                }
+                let cell = row as usize * columns + column as usize;
+                let (cx, cy) = (
+                    x - left - column * (CELL_SIZE + CELL_GAP),
+                    y - top - row * (CELL_SIZE + CELL_GAP),
+                );
+                if cell >= count || cx >= CELL_SIZE || cy >= CELL_SIZE {
+                    return Luma([0]);
+                }
+                if cell_contains(settings, &labels[cell], cx, cy, z) {
+                    Luma([cell as u8 + 1])
+                } else {
+                    Luma([0])
+                }
+            });
+            let exposure = |first_cell: usize| {
+                let mut image = cells.clone();
+                for pixel in image.pixels_mut() {
+                    pixel.0[0] = if pixel.0[0] as usize > first_cell {
+                        255
+                    } else {
+                        0
+                    };
+                }
+                image
+            };
+            on_layer(index);
+            if index < print.bottom_layers {
+                vec![new_layer(
+                    file_ref,
+                    &exposure(0),
+                    print.bottom_exposure_time,
+                    print.layer_height,
+                )]
+            } else {
+                // The first exposure moves up a layer, the others are skipped if they add nothing
+                (0..count)
+                    .filter(|cell| *cell == 0 || exposure_times[*cell] > exposure_times[cell - 1])
+                    .map(|cell| {
+                        let (time, height) = match cell {
+                            0 => (exposure_times[0], print.layer_height),
+                            _ => (exposure_times[cell] - exposure_times[cell - 1], 0.0),
+                        };
+                        new_layer(file_ref, &exposure(cell), time, height)
+                    })
+                    .collect()
+            }
+        })
+        .collect();
+    file.layers = layers.into_iter().flatten().collect();
+    file.header.volume = compute_volume(&file);
+    Ok(file)
+}
+
+#[test]
+fn test_generate_calibration() {
+    use crate::generate::test_printer;
+
+    let printer = Printer {
+        pixel_size: 500.0,
+        ..test_printer(64, 32)
+    };
+    let print = PrintSettings {
+        layer_height: 0.5,
+        bottom_layers: 1,
+        ..PrintSettings::default()
+    };
+    let settings = |exposure_times: Vec<f32>| Calibration {
+        exposure_times,
+        base_height: 1.0,
+        feature_height: 1.0,
+    };
+    // Equal exposures need no sub-exposure, every layer still moves up
+    let file = generate_calibration(&printer, &print, &settings(vec![6.0, 6.0]), |_| {}).unwrap();
+    assert_eq!(file.layers.len(), 4);
+    assert!(file.layers.iter().all(|layer| layer.layer_height == 0.5));
+    let file = generate_calibration(&printer, &print, &settings(vec![8.0, 6.0]), |_| {}).unwrap();
+    let exposures: Vec<f32> = file
+        .layers
+        .iter()
+        .map(|layer| layer.exposure_time)
+        .collect();
+    assert_eq!(exposures, vec![60.0, 6.0, 2.0, 6.0, 2.0, 6.0, 2.0]);
+    for exposure_times in [vec![0.0, 6.0], vec![f32::NAN, 6.0], vec![6.0; 256]] {
+        assert!(generate_calibration(&printer, &print, &settings(exposure_times), |_| {}).is_err());
+    }
+    let flat = PrintSettings {
+        layer_height: 0.0,
+        ..print
+    };
+    assert!(generate_calibration(&printer, &flat, &settings(vec![6.0]), |_| {}).is_err());
+}
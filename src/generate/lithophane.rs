@@ -0,0 +1,231 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{PwsFile, PwsLayer};
+use crate::generate::{new_job, new_layer, PrintSettings};
+use crate::printer::Printer;
+use crate::transform::geometry::fit_image;
+use image::{DynamicImage, GrayImage, Luma};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Dark parts of the image become thick, bright parts thin. The panel is described in surface
+ * coordinates (u, v) in mm, u running along the image width and v down along its height.
+ * Flat panels lie on the plate with the relief pointing up. Curved panels stand upright on the
+ * plate, bent around a vertical axis, so every layer is one row of the panel.
+ */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Shape {
+    Flat,
+    Curved { angle: f32 }, // Angle covered by the panel, in degrees
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Lithophane {
+    pub width: f32,         // Width of the image, in mm
+    pub min_thickness: f32, // Thickness of white, in mm
+    pub max_thickness: f32, // Thickness of black, in mm
+    pub frame: f32,         // Width of a border at full thickness, in mm
+    pub shape: Shape,
+}
+
+impl Lithophane {
+    /// Height of the printed part in mm, for an image of the given size in pixels.
+    pub fn height(&self, image_width: u32, image_height: u32) -> f32 {
+        match self.shape {
+            Shape::Flat => self.max_thickness,
+            Shape::Curved { .. } => {
+                image_height as f32 * self.width / image_width as f32 + 2.0 * self.frame
+            }
+        }
+    }
+}
+
+struct Panel<'a> {
+    image: &'a GrayImage,
+    settings: &'a Lithophane,
+    scale: f32, // Image pixels per mm
+    width: f32,
+    height: f32,
+}
+
+impl<'a> Panel<'a> {
+    fn new(image: &'a GrayImage, settings: &'a Lithophane) -> Panel<'a> {
+        let scale = image.width() as f32 / settings.width;
+        Panel {
+            image,
+            settings,
+            scale,
+            width: settings.width + 2.0 * settings.frame,
+            height: image.height() as f32 / scale + 2.0 * settings.frame,
+        }
+    }
+
+    /// Bilinearly interpolated brightness of the image at (x, y) in image pixels.
+    fn brightness(&self, x: f32, y: f32) -> f32 {
+        let max_x = self.image.width() - 1;
+        let max_y = self.image.height() - 1;
+        let (x, y) = ((x - 0.5).max(0.0), (y - 0.5).max(0.0));
+        let (x0, y0) = (
+            std::cmp::min(x as u32, max_x),
+            std::cmp::min(y as u32, max_y),
+        );
+        let (x1, y1) = (std::cmp::min(x0 + 1, max_x), std::cmp::min(y0 + 1, max_y));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let value = |x, y| self.image.get_pixel(x, y).0[0] as f32 / 255.0;
+        let top = value(x0, y0) * (1.0 - fx) + value(x1, y0) * fx;
+        let bottom = value(x0, y1) * (1.0 - fx) + value(x1, y1) * fx;
+        (top * (1.0 - fy) + bottom * fy).clamp(0.0, 1.0)
+    }
+
+    /// Thickness at (u, v) in mm, or None outside of the panel.
+    fn thickness(&self, u: f32, v: f32) -> Option<f32> {
+        let settings = self.settings;
+        if u < 0.0 || v < 0.0 || u >= self.width || v >= self.height {
+            return None;
+        }
+        let (x, y) = (u - settings.frame, v - settings.frame);
+        if x < 0.0 || y < 0.0 || x >= self.width - 2.0 * settings.frame {
+            return Some(settings.max_thickness);
+        }
+        if y >= self.height - 2.0 * settings.frame {
+            return Some(settings.max_thickness);
+        }
+        let brightness = self.brightness(x * self.scale, y * self.scale);
+        Some(
+            settings.max_thickness - brightness * (settings.max_thickness - settings.min_thickness),
+        )
+    }
+}
+
+/// Builds the lithophane in the centre of the plate, with the source image as preview.
+pub fn generate_lithophane<F>(
+    printer: &Printer,
+    print: &PrintSettings,
+    image: &DynamicImage,
+    settings: &Lithophane,
+    on_layer: F,
+) -> Result<PwsFile, String>
+where
+    F: Fn(usize) + Sync,
+{
+    if settings.min_thickness <= 0.0 || settings.max_thickness < settings.min_thickness {
+        return Err("Thickness should be positive, and the maximum at least the minimum".into());
+    }
+    if !(settings.width > 0.0 && settings.width.is_finite()) {
+        return Err(format!("Width of {} mm should be positive", settings.width));
+    }
+    if !(settings.frame >= 0.0 && settings.frame.is_finite()) {
+        return Err(format!(
+            "Frame of {} mm should not be negative",
+            settings.frame
+        ));
+    }
+    let source = image.to_luma();
+    let panel = Panel::new(&source, settings);
+    let pixel_size = printer.pixel_size_mm();
+    let (plate_width, plate_height) = (
+        printer.width as f32 * pixel_size,
+        printer.height as f32 * pixel_size,
+    );
+    let thickness = settings.max_thickness;
+    let total_height = settings.height(source.width(), source.height());
+    let footprint = match settings.shape {
+        Shape::Flat => (panel.width, panel.height),
+        Shape::Curved { angle } => {
+            if angle <= 0.0 || angle > 180.0 {
+                return Err("Curve angle should be between 0 and 180 degrees".into());
+            }
+            let half_angle = angle.to_radians() / 2.0;
+            let radius = panel.width / angle.to_radians();
+            (
+                2.0 * (radius + thickness) * half_angle.sin(),
+                radius + thickness - radius * half_angle.cos(),
+            )
+        }
+    };
+    if footprint.0 > plate_width || footprint.1 > plate_height {
+        return Err(format!(
+            "Lithophane needs {:.1} x {:.1} mm, but the plate is only {:.1} x {:.1} mm",
+            footprint.0, footprint.1, plate_width, plate_height
+        ));
+    }
+    if total_height > printer.max_z {
+        return Err(format!(
+            "Lithophane is {:.1} mm high, but the printer only reaches {:.1} mm",
+            total_height, printer.max_z
+        ));
+    }
+    let left = (plate_width - footprint.0) / 2.0;
+    let top = (plate_height - footprint.1) / 2.0;
+
+    let mut file = new_job(printer, print, 1);
+    file.preview = fit_image(&image.to_rgb(), file.preview.width(), file.preview.height());
+    let num_layers = (total_height / print.layer_height).ceil() as usize;
+    let file_ref = &file;
+    let panel_ref = &panel;
+    let layers: Vec<PwsLayer> = (0..num_layers)
+        .into_par_iter()
+        .map(|index| {
+            let z = (index as f32 + 0.5) * print.layer_height;
+            let layer = GrayImage::from_fn(printer.width, printer.height, |x, y| {
+                let (x, y) = (
+                    (x as f32 + 0.5) * pixel_size - left,
+                    (y as f32 + 0.5) * pixel_size - top,
+                );
+                let lit = match settings.shape {
+                    Shape::Flat => panel_ref.thickness(x, y).is_some_and(|t| t > z),
+                    Shape::Curved { angle } => {
+                        // Centre of the bend, the top of the arc touches the top of the footprint
+                        let radius = panel_ref.width / angle.to_radians();
+                        let (dx, dy) = (x - footprint.0 / 2.0, y - (radius + thickness));
+                        let distance = (dx * dx + dy * dy).sqrt();
+                        let u = (dx.atan2(-dy) + angle.to_radians() / 2.0) * radius;
+                        panel_ref
+                            .thickness(u, panel_ref.height - z)
+                            .is_some_and(|t| distance >= radius && distance < radius + t)
+                    }
+                };
+                Luma([if lit { 255 } else { 0 }])
+            });
+            on_layer(index);
+            let exposure_time = if index < print.bottom_layers {
+                print.bottom_exposure_time
+            } else {
+                print.exposure_time
+            };
+            new_layer(file_ref, &layer, exposure_time, print.layer_height)
+        })
+        .collect();
+    file.layers = layers;
+    file.header.volume = compute_volume(&file);
+    Ok(file)
+}
+
+#[test]
+fn test_lithophane_settings() {
+    use crate::generate::test_printer;
+    use image::GrayImage;
+
+    let image = DynamicImage::ImageLuma8(GrayImage::new(4, 4));
+    let settings = |width, frame| Lithophane {
+        width,
+        min_thickness: 0.8,
+        max_thickness: 3.0,
+        frame,
+        shape: Shape::Flat,
+    };
+    let generate = |settings: &Lithophane| {
+        generate_lithophane(
+            &test_printer(20, 20),
+            &PrintSettings::default(),
+            &image,
+            settings,
+            |_| {},
+        )
+    };
+    assert!(generate(&settings(0.0, 0.0)).is_err());
+    assert!(generate(&settings(-4.0, 0.0)).is_err());
+    assert!(generate(&settings(4.0, -1.0)).is_err());
+    let file = generate(&settings(4.0, 1.0)).unwrap();
+    assert_eq!(file.layers.len(), 60);
+}
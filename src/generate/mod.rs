@@ -0,0 +1,103 @@
+use crate::formats::pws::data::{CompressedBitstream, PwsFile, PwsHeader, PwsLayer};
+use crate::printer::Printer;
+use image::{GrayImage, Rgb, RgbImage};
+
+pub mod calibration;
+pub mod lithophane;
+
+/*
+ * Generators build print jobs directly from a description of the part, without going through a
+ * slicer. Jobs are created for a printer from the presets in `printer`.
+ */
+
+#[derive(Clone, Copy, Debug)]
+pub struct PrintSettings {
+    pub layer_height: f32,         // in mm
+    pub exposure_time: f32,        // in sec
+    pub bottom_exposure_time: f32, // in sec
+    pub bottom_layers: usize,
+    pub lift_distance: f32, // in mm
+    pub lift_speed: f32,    // in mm/sec
+    pub drop_speed: f32,    // in mm/sec
+}
+
+impl Default for PrintSettings {
+    fn default() -> PrintSettings {
+        PrintSettings {
+            layer_height: 0.05,
+            exposure_time: 8.0,
+            bottom_exposure_time: 60.0,
+            bottom_layers: 6,
+            lift_distance: 6.0,
+            lift_speed: 1.5,
+            drop_speed: 2.5,
+        }
+    }
+}
+
+/// A job without any layers for the printer, with a black preview.
+pub fn new_job(printer: &Printer, settings: &PrintSettings, bits_per_pixel: u32) -> PwsFile {
+    let header = PwsHeader {
+        pixel_size: printer.pixel_size,
+        layer_height: settings.layer_height,
+        exposure_time: settings.exposure_time,
+        off_time: 1.0,
+        bottom_exposure_time: settings.bottom_exposure_time,
+        num_bottom_layers: settings.bottom_layers as f32,
+        lift_distance: settings.lift_distance,
+        lift_speed: settings.lift_speed,
+        drop_speed: settings.drop_speed,
+        volume: 0.0,
+        bits_per_pixel,
+        width: printer.width,
+        height: printer.height,
+        weight: 0.0,
+        price: 0.0,
+        resin_type: 36,
+        use_individual_parameters: true,
+    };
+    PwsFile {
+        header,
+        preview: RgbImage::from_pixel(224, 168, Rgb([0, 0, 0])),
+        layers: Vec::new(),
+    }
+}
+
+/// Layer with the lift settings of the job.
+pub fn new_layer(
+    file: &PwsFile,
+    image: &GrayImage,
+    exposure_time: f32,
+    layer_height: f32,
+) -> PwsLayer {
+    PwsLayer {
+        lift_distance: file.header.lift_distance,
+        lift_speed: file.header.lift_speed,
+        exposure_time,
+        layer_height,
+        data: CompressedBitstream::from_image(image, file.header.bits_per_pixel as usize),
+    }
+}
+
+/// Printer with 1 mm pixels for tests.
+#[cfg(test)]
+pub fn test_printer(width: u32, height: u32) -> Printer {
+    Printer {
+        name: "test",
+        width,
+        height,
+        pixel_size: 1000.0,
+        max_z: 10.0,
+    }
+}
+
+/// Job on a `test_printer` with 8 sec, 0.05 mm layers of `images`.
+#[cfg(test)]
+pub fn test_job(width: u32, height: u32, images: &[GrayImage]) -> PwsFile {
+    let mut file = new_job(&test_printer(width, height), &PrintSettings::default(), 1);
+    file.layers = images
+        .iter()
+        .map(|image| new_layer(&file, image, 8.0, 0.05))
+        .collect();
+    file
+}
@@ -4,8 +4,15 @@ extern crate nom;
 //extern crate zip;
 //
 
+pub mod analysis;
 pub mod formats;
+pub mod gen_rgb565;
+pub mod generate;
 pub mod parse_rgb565;
+pub mod printer;
+pub mod raster;
+pub mod report;
+pub mod transform;
 
 /*
 use crate::formats::photons;
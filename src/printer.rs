@@ -0,0 +1,47 @@
+/// Physical properties of a printer's LCD and Z axis.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Printer {
+    pub name: &'static str,
+    pub width: u32,      // in pixels
+    pub height: u32,     // in pixels
+    pub pixel_size: f32, // in um
+    pub max_z: f32,      // in mm
+}
+
+pub const PRINTERS: &[Printer] = &[
+    Printer {
+        name: "photon-s",
+        width: 1440,
+        height: 2560,
+        pixel_size: 47.25,
+        max_z: 165.0,
+    },
+    Printer {
+        name: "photon-mono-x",
+        width: 3840,
+        height: 2400,
+        pixel_size: 50.0,
+        max_z: 245.0,
+    },
+    Printer {
+        name: "sl1",
+        width: 1440,
+        height: 2560,
+        pixel_size: 47.25,
+        max_z: 150.0,
+    },
+];
+
+impl Printer {
+    pub fn by_name(name: &str) -> Option<&'static Printer> {
+        PRINTERS.iter().find(|printer| printer.name == name)
+    }
+
+    pub fn names() -> Vec<&'static str> {
+        PRINTERS.iter().map(|printer| printer.name).collect()
+    }
+
+    pub fn pixel_size_mm(&self) -> f32 {
+        self.pixel_size / 1000.0
+    }
+}
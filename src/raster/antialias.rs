@@ -0,0 +1,134 @@
+use image::GrayImage;
+
+/*
+ * Anti-aliasing for layers that are pure black and white. Both methods only add grey levels
+ * around edges, solid areas stay solid.
+ */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Antialias {
+    // Box blur over `radius` pixels. Blurred pixels stay on the side of half intensity their
+    // source pixel was on, so the outline does not move and thin features are not lost.
+    Blur { radius: u32 },
+    // Smooths the outline by interpolating the layer `factor` times finer, thresholding it, and
+    // averaging back down to the original pixels.
+    Supersample { factor: u32 },
+}
+
+/// Whether every pixel is either black or white.
+pub fn is_binary(image: &GrayImage) -> bool {
+    image.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255)
+}
+
+/// Sums of every horizontal window of `2 * radius + 1` pixels, clipped at the edges.
+fn box_sum_rows(values: &[u32], width: usize, radius: usize) -> Vec<u32> {
+    let mut output = vec![0; values.len()];
+    for (row, output) in values.chunks(width).zip(output.chunks_mut(width)) {
+        let mut prefix = vec![0; width + 1];
+        for (index, value) in row.iter().enumerate() {
+            prefix[index + 1] = prefix[index] + value;
+        }
+        for (x, output) in output.iter_mut().enumerate() {
+            let start = x.saturating_sub(radius);
+            let end = std::cmp::min(x + radius + 1, width);
+            *output = prefix[end] - prefix[start];
+        }
+    }
+    output
+}
+
+fn blur(image: &GrayImage, radius: u32) -> GrayImage {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let radius = radius as usize;
+    let values: Vec<u32> = image.pixels().map(|p| p.0[0] as u32).collect();
+    let rows = box_sum_rows(&values, width, radius);
+    // Transpose to run the same pass over the columns
+    let mut transposed = vec![0; rows.len()];
+    for y in 0..height {
+        for x in 0..width {
+            transposed[x * height + y] = rows[y * width + x];
+        }
+    }
+    let columns = box_sum_rows(&transposed, height, radius);
+    let mut output = image.clone();
+    for (index, pixel) in output.pixels_mut().enumerate() {
+        let (x, y) = (index % width, index / width);
+        let count_x = std::cmp::min(x + radius + 1, width) - x.saturating_sub(radius);
+        let count_y = std::cmp::min(y + radius + 1, height) - y.saturating_sub(radius);
+        let average = columns[x * height + y] / (count_x * count_y) as u32;
+        pixel.0[0] = if pixel.0[0] >= 128 {
+            std::cmp::max(average, 128) as u8
+        } else {
+            std::cmp::min(average, 127) as u8
+        };
+    }
+    output
+}
+
+fn supersample(image: &GrayImage, factor: u32) -> GrayImage {
+    let (width, height) = image.dimensions();
+    let lit = |x: i64, y: i64| {
+        let x = x.clamp(0, width as i64 - 1) as u32;
+        let y = y.clamp(0, height as i64 - 1) as u32;
+        image.get_pixel(x, y).0[0] as f32 / 255.0
+    };
+    GrayImage::from_fn(width, height, |x, y| {
+        let pixel = image.get_pixel(x, y).0[0];
+        // Bilinear interpolation only changes anything next to an edge
+        let neighbours = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+        if neighbours
+            .iter()
+            .all(|(dx, dy)| lit(x as i64 + dx, y as i64 + dy) == pixel as f32 / 255.0)
+        {
+            return image::Luma([pixel]);
+        }
+        let mut count = 0;
+        for sy in 0..factor {
+            for sx in 0..factor {
+                // Position relative to the pixel centres around the sample
+                let px = x as f32 + (sx as f32 + 0.5) / factor as f32 - 0.5;
+                let py = y as f32 + (sy as f32 + 0.5) / factor as f32 - 0.5;
+                let (x0, y0) = (px.floor(), py.floor());
+                let (fx, fy) = (px - x0, py - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = lit(x0, y0) * (1.0 - fx) + lit(x0 + 1, y0) * fx;
+                let bottom = lit(x0, y0 + 1) * (1.0 - fx) + lit(x0 + 1, y0 + 1) * fx;
+                if top * (1.0 - fy) + bottom * fy >= 0.5 {
+                    count += 1;
+                }
+            }
+        }
+        image::Luma([(255 * count / (factor * factor)) as u8])
+    })
+}
+
+/// Adds grey levels around the edges of a black and white layer. Layers that already have grey
+/// levels are returned as they are.
+pub fn synthesize_antialiasing(image: &GrayImage, method: Antialias) -> GrayImage {
+    if !is_binary(image) {
+        return image.clone();
+    }
+    match method {
+        Antialias::Blur { radius } => blur(image, radius),
+        Antialias::Supersample { factor } => supersample(image, std::cmp::max(factor, 1)),
+    }
+}
+
+#[test]
+fn test_synthesize_antialiasing() {
+    // A single lit pixel survives blurring, a solid area stays solid
+    let mut image = GrayImage::new(7, 5);
+    image.put_pixel(1, 2, image::Luma([255]));
+    for y in 0..5 {
+        for x in 4..7 {
+            image.put_pixel(x, y, image::Luma([255]));
+        }
+    }
+    let blurred = synthesize_antialiasing(&image, Antialias::Blur { radius: 1 });
+    assert_eq!(blurred.get_pixel(1, 2).0[0], 128);
+    assert_eq!(blurred.get_pixel(6, 2).0[0], 255);
+    assert_eq!(blurred.get_pixel(3, 2).0[0], 85);
+    // Layers with grey levels are left alone
+    let again = synthesize_antialiasing(&blurred, Antialias::Blur { radius: 1 });
+    assert_eq!(again.into_raw(), blurred.into_raw());
+}
@@ -0,0 +1,209 @@
+use crate::raster::Bitmap;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoundingBox {
+    pub min_x: u32,
+    pub min_y: u32,
+    pub max_x: u32, // inclusive
+    pub max_y: u32, // inclusive
+}
+
+impl BoundingBox {
+    pub fn new(x: u32, y: u32) -> BoundingBox {
+        BoundingBox {
+            min_x: x,
+            min_y: y,
+            max_x: x,
+            max_y: y,
+        }
+    }
+
+    fn extend(&mut self, x: u32, y: u32) {
+        self.min_x = std::cmp::min(self.min_x, x);
+        self.min_y = std::cmp::min(self.min_y, y);
+        self.max_x = std::cmp::max(self.max_x, x);
+        self.max_y = std::cmp::max(self.max_y, y);
+    }
+
+    pub fn union(&self, other: &BoundingBox) -> BoundingBox {
+        BoundingBox {
+            min_x: std::cmp::min(self.min_x, other.min_x),
+            min_y: std::cmp::min(self.min_y, other.min_y),
+            max_x: std::cmp::max(self.max_x, other.max_x),
+            max_y: std::cmp::max(self.max_y, other.max_y),
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.max_x - self.min_x + 1
+    }
+
+    pub fn height(&self) -> u32 {
+        self.max_y - self.min_y + 1
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Component {
+    pub area: usize, // in pixels
+    pub sum_x: u64,
+    pub sum_y: u64,
+    pub bounds: BoundingBox,
+}
+
+impl Component {
+    pub fn centroid(&self) -> (f32, f32) {
+        (
+            self.sum_x as f32 / self.area as f32,
+            self.sum_y as f32 / self.area as f32,
+        )
+    }
+}
+
+pub struct Labels {
+    pub width: u32,
+    pub height: u32,
+    pub labels: Vec<u32>, // 0 for background, component index + 1 otherwise
+    pub components: Vec<Component>,
+}
+
+impl Labels {
+    pub fn get(&self, x: u32, y: u32) -> Option<usize> {
+        match self.labels[(y * self.width + x) as usize] {
+            0 => None,
+            label => Some(label as usize - 1),
+        }
+    }
+}
+
+fn find(parents: &mut [u32], label: u32) -> u32 {
+    let mut root = label;
+    while parents[root as usize] != root {
+        root = parents[root as usize];
+    }
+    let mut current = label;
+    while parents[current as usize] != root {
+        let next = parents[current as usize];
+        parents[current as usize] = root;
+        current = next;
+    }
+    root
+}
+
+fn union(parents: &mut [u32], a: u32, b: u32) -> u32 {
+    let a = find(parents, a);
+    let b = find(parents, b);
+    let (low, high) = if a < b { (a, b) } else { (b, a) };
+    parents[high as usize] = low;
+    low
+}
+
+/// Labels all 8-connected components of pixels equal to `value`.
+pub fn label(bitmap: &Bitmap, value: bool) -> Labels {
+    label_connected(bitmap, value, true)
+}
+
+/// Labels all 4-connected components of pixels equal to `value`. Use this for the gaps between
+/// 8-connected components, which diagonal steps do not connect.
+pub fn label_4_connected(bitmap: &Bitmap, value: bool) -> Labels {
+    label_connected(bitmap, value, false)
+}
+
+/// Two-pass union-find, so memory use stays linear in the image size.
+fn label_connected(bitmap: &Bitmap, value: bool, diagonal: bool) -> Labels {
+    let width = bitmap.width;
+    let height = bitmap.height;
+    let mut labels = vec![0u32; bitmap.data.len()];
+    let mut parents: Vec<u32> = vec![0];
+    for y in 0..height {
+        for x in 0..width {
+            let index = bitmap.index(x, y);
+            if bitmap.data[index] != value {
+                continue;
+            }
+            let mut current = 0;
+            let mut neighbours = [None; 4];
+            if x > 0 {
+                neighbours[0] = Some(index - 1);
+            }
+            if y > 0 {
+                let above = index - width as usize;
+                neighbours[1] = Some(above);
+                if diagonal && x > 0 {
+                    neighbours[2] = Some(above - 1);
+                }
+                if diagonal && x + 1 < width {
+                    neighbours[3] = Some(above + 1);
+                }
+            }
+            for neighbour in neighbours.iter().filter_map(|n| *n) {
+                let neighbour_label = labels[neighbour];
+                if neighbour_label == 0 {
+                    continue;
+                }
+                current = if current == 0 {
+                    find(&mut parents, neighbour_label)
+                } else {
+                    union(&mut parents, current, neighbour_label)
+                };
+            }
+            if current == 0 {
+                current = parents.len() as u32;
+                parents.push(current);
+            }
+            labels[index] = current;
+        }
+    }
+
+    // Second pass: flatten to consecutive component indices and gather statistics.
+    let mut remap = vec![0u32; parents.len()];
+    let mut components: Vec<Component> = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            let index = bitmap.index(x, y);
+            if labels[index] == 0 {
+                continue;
+            }
+            let root = find(&mut parents, labels[index]) as usize;
+            if remap[root] == 0 {
+                components.push(Component {
+                    area: 0,
+                    sum_x: 0,
+                    sum_y: 0,
+                    bounds: BoundingBox::new(x, y),
+                });
+                remap[root] = components.len() as u32;
+            }
+            let label = remap[root];
+            labels[index] = label;
+            let component = &mut components[label as usize - 1];
+            component.area += 1;
+            component.sum_x += x as u64;
+            component.sum_y += y as u64;
+            component.bounds.extend(x, y);
+        }
+    }
+    Labels {
+        width,
+        height,
+        labels,
+        components,
+    }
+}
+
+#[test]
+fn test_label() {
+    // Two blobs, the right one is diagonally connected.
+    let mut bitmap = Bitmap::new(6, 3);
+    bitmap.set(0, 0, true);
+    bitmap.set(0, 1, true);
+    bitmap.set(3, 0, true);
+    bitmap.set(4, 1, true);
+    bitmap.set(5, 2, true);
+    let labels = label(&bitmap, true);
+    assert_eq!(labels.components.len(), 2);
+    assert_eq!(labels.components[0].area, 2);
+    assert_eq!(labels.components[1].area, 3);
+    assert_eq!(labels.get(5, 2), Some(1));
+    assert_eq!(labels.get(1, 1), None);
+}
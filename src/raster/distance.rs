@@ -0,0 +1,93 @@
+use crate::raster::Bitmap;
+
+/*
+ * Exact Euclidean distance transform, as described in "Distance Transforms of Sampled Functions"
+ * by Felzenszwalb and Huttenlocher. Runs a 1D transform over all columns, then over all rows.
+ */
+
+// Stands in for infinity in the input, as real infinities would turn intersections into NaN.
+const INFINITY: f32 = 1e20;
+
+fn transform_1d(f: &[f32], d: &mut [f32], v: &mut [usize], z: &mut [f32]) {
+    let intersection = |q: usize, p: usize| {
+        ((f[q] + (q * q) as f32) - (f[p] + (p * p) as f32)) / (2.0 * (q as f32 - p as f32))
+    };
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = -f32::INFINITY;
+    z[1] = f32::INFINITY;
+    for q in 1..f.len() {
+        let mut s = intersection(q, v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = intersection(q, v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f32::INFINITY;
+    }
+    k = 0;
+    for (q, value) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f32 {
+            k += 1;
+        }
+        let p = v[k];
+        let delta = q as f32 - p as f32;
+        *value = delta * delta + f[p];
+    }
+}
+
+/// Squared distance in pixels from every pixel to the nearest pixel equal to `target`. Pixels
+/// outside of the image are not taken into account. If no pixel equals `target`, all distances
+/// are huge.
+pub fn squared_distance_to(bitmap: &Bitmap, target: bool) -> Vec<f32> {
+    let width = bitmap.width as usize;
+    let height = bitmap.height as usize;
+    let mut distances: Vec<f32> = bitmap
+        .data
+        .iter()
+        .map(|v| if *v == target { 0.0 } else { INFINITY })
+        .collect();
+    let longest = std::cmp::max(width, height);
+    let mut f = vec![0.0; longest];
+    let mut d = vec![0.0; longest];
+    let mut v = vec![0; longest];
+    let mut z = vec![0.0; longest + 1];
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = distances[y * width + x];
+        }
+        transform_1d(&f[..height], &mut d[..height], &mut v, &mut z);
+        for y in 0..height {
+            distances[y * width + x] = d[y];
+        }
+    }
+    for y in 0..height {
+        let row = &mut distances[y * width..(y + 1) * width];
+        f[..width].copy_from_slice(row);
+        transform_1d(&f[..width], row, &mut v, &mut z);
+    }
+    distances
+}
+
+/// Distance in pixels from every pixel to the nearest pixel equal to `target`.
+pub fn distance_to(bitmap: &Bitmap, target: bool) -> Vec<f32> {
+    squared_distance_to(bitmap, target)
+        .into_iter()
+        .map(f32::sqrt)
+        .collect()
+}
+
+#[test]
+fn test_distance_to() {
+    let mut bitmap = Bitmap::new(5, 4);
+    bitmap.set(1, 1, true);
+    bitmap.set(4, 3, true);
+    let distances = squared_distance_to(&bitmap, true);
+    assert_eq!(distances[bitmap.index(1, 1)], 0.0);
+    assert_eq!(distances[bitmap.index(0, 0)], 2.0);
+    assert_eq!(distances[bitmap.index(3, 1)], 4.0);
+    assert_eq!(distances[bitmap.index(4, 2)], 1.0);
+    assert_eq!(distances[bitmap.index(1, 3)], 4.0);
+}
@@ -0,0 +1,84 @@
+use image::GrayImage;
+
+/*
+ * Dithering spreads the rounding error of reducing grey values to a few anti-aliasing levels
+ * over neighbouring pixels. Output values are exactly the grey values `to_image` decodes the
+ * levels to, so `from_image` encodes them without further rounding. Black and white pixels are
+ * left as they are and receive no error, so no stray pixels appear outside of the part.
+ */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Dither {
+    FloydSteinberg,
+    Bayer, // Ordered dithering with a 4x4 matrix
+}
+
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn level_value(level: usize, levels: usize) -> u8 {
+    (level * 255 / levels) as u8
+}
+
+/// Reduces the grey values to `levels` + 1 values, one per number of lit bit planes.
+pub fn dither(image: &GrayImage, levels: usize, method: Dither) -> GrayImage {
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let scale = levels as f32 / 255.0;
+    let mut output = image.clone();
+    match method {
+        Dither::Bayer => {
+            for (x, y, pixel) in output.enumerate_pixels_mut() {
+                let threshold = (BAYER[y as usize % 4][x as usize % 4] as f32 + 0.5) / 16.0;
+                let level = (pixel.0[0] as f32 * scale + threshold).floor() as usize;
+                pixel.0[0] = level_value(std::cmp::min(level, levels), levels);
+            }
+        }
+        Dither::FloydSteinberg => {
+            let mut values: Vec<f32> = image.pixels().map(|p| p.0[0] as f32).collect();
+            let output: &mut [u8] = &mut output;
+            let source: &[u8] = image;
+            let partial = |index: usize| source[index] != 0 && source[index] != 255;
+            for y in 0..height {
+                for x in 0..width {
+                    let index = y * width + x;
+                    if !partial(index) {
+                        continue;
+                    }
+                    let level = (values[index] * scale).round().clamp(0.0, levels as f32);
+                    let quantized = level_value(level as usize, levels);
+                    let error = values[index] - quantized as f32;
+                    output[index] = quantized;
+                    let mut spread = |dx: isize, dy: usize, weight: f32| {
+                        let (nx, ny) = (x as isize + dx, y + dy);
+                        if nx >= 0 && (nx as usize) < width && ny < height {
+                            let neighbour = ny * width + nx as usize;
+                            if partial(neighbour) {
+                                values[neighbour] += error * weight;
+                            }
+                        }
+                    };
+                    spread(1, 0, 7.0 / 16.0);
+                    spread(-1, 1, 3.0 / 16.0);
+                    spread(0, 1, 5.0 / 16.0);
+                    spread(1, 1, 1.0 / 16.0);
+                }
+            }
+        }
+    }
+    output
+}
+
+#[test]
+fn test_dither() {
+    // A flat quarter intensity on a single level lights a quarter of the pixels
+    let image = GrayImage::from_pixel(8, 8, image::Luma([64]));
+    for method in [Dither::FloydSteinberg, Dither::Bayer].iter() {
+        let dithered = dither(&image, 1, *method);
+        let lit = dithered.pixels().filter(|p| p.0[0] == 255).count();
+        assert!(dithered.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255));
+        assert!((15..=17).contains(&lit), "{:?} lit {} pixels", method, lit);
+    }
+    // Black and white stay as they are
+    let image = GrayImage::from_raw(3, 1, vec![0, 255, 0]).unwrap();
+    let dithered = dither(&image, 4, Dither::FloydSteinberg);
+    assert_eq!(dithered.into_raw(), vec![0, 255, 0]);
+}
@@ -0,0 +1,84 @@
+use crate::raster::components::BoundingBox;
+use image::GrayImage;
+
+pub mod antialias;
+pub mod components;
+pub mod distance;
+pub mod dither;
+pub mod font;
+pub mod morphology;
+pub mod resample;
+
+/// Binary version of a layer, true for every pixel that receives any light.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Bitmap {
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<bool>,
+}
+
+impl Bitmap {
+    pub fn new(width: u32, height: u32) -> Bitmap {
+        Bitmap {
+            width,
+            height,
+            data: vec![false; (width * height) as usize],
+        }
+    }
+
+    pub fn from_image(image: &GrayImage) -> Bitmap {
+        Bitmap {
+            width: image.width(),
+            height: image.height(),
+            data: image.pixels().map(|p| p.0[0] > 0).collect(),
+        }
+    }
+
+    pub fn to_image(&self) -> GrayImage {
+        let pixels = self.data.iter().map(|v| if *v { 255 } else { 0 }).collect();
+        GrayImage::from_raw(self.width, self.height, pixels).unwrap()
+    }
+
+    pub fn index(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.data[self.index(x, y)]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, value: bool) {
+        let index = self.index(x, y);
+        self.data[index] = value;
+    }
+
+    pub fn count(&self) -> usize {
+        self.data.iter().filter(|v| **v).count()
+    }
+}
+
+/// Copies the `region` of `source` into `target` with its top-left corner at (x, y), keeping the
+/// brightest value where lit pixels overlap. Pixels falling outside of `target` are dropped.
+pub fn copy_region(
+    target: &mut GrayImage,
+    source: &GrayImage,
+    region: &BoundingBox,
+    x: u32,
+    y: u32,
+) {
+    for sy in region.min_y..=region.max_y {
+        let ty = y + (sy - region.min_y);
+        if ty >= target.height() {
+            break;
+        }
+        for sx in region.min_x..=region.max_x {
+            let tx = x + (sx - region.min_x);
+            if tx >= target.width() {
+                break;
+            }
+            let value = source.get_pixel(sx, sy).0[0];
+            let pixel = target.get_pixel_mut(tx, ty);
+            pixel.0[0] = std::cmp::max(pixel.0[0], value);
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::raster::distance::distance_to;
+use crate::raster::Bitmap;
+use image::GrayImage;
+
+/*
+ * Both operations work on the distance transform rather than a structuring element, so large radii
+ * stay cheap. The new edge gets a one pixel anti-aliased ramp for fractional radii.
+ */
+
+fn coverage(value: f32) -> f32 {
+    value.clamp(0.0, 1.0)
+}
+
+/// Shrinks lit areas by `radius` pixels.
+pub fn erode(image: &GrayImage, radius: f32) -> GrayImage {
+    let distances = distance_to(&Bitmap::from_image(image), false);
+    let mut output = image.clone();
+    for (pixel, distance) in output.pixels_mut().zip(distances) {
+        pixel.0[0] = (pixel.0[0] as f32 * coverage(distance - radius)).round() as u8;
+    }
+    output
+}
+
+/// Grows lit areas by `radius` pixels.
+pub fn dilate(image: &GrayImage, radius: f32) -> GrayImage {
+    let distances = distance_to(&Bitmap::from_image(image), true);
+    let mut output = image.clone();
+    for (pixel, distance) in output.pixels_mut().zip(distances) {
+        let grown = (255.0 * coverage(radius + 1.0 - distance)).round() as u8;
+        pixel.0[0] = std::cmp::max(pixel.0[0], grown);
+    }
+    output
+}
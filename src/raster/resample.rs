@@ -0,0 +1,96 @@
+use image::GrayImage;
+
+/*
+ * Area-averaging resampler between pixel grids of different pitch. Every output pixel is the
+ * average of the source area it covers, so anti-aliased edges survive both up- and downscaling.
+ * The centres of both grids are aligned.
+ */
+
+/// For every output pixel along one axis, the source pixels it covers and their weights.
+fn axis_weights(
+    source_length: u32,
+    source_pitch: f32,
+    target_length: u32,
+    target_pitch: f32,
+) -> Vec<Vec<(u32, f32)>> {
+    let scale = target_pitch / source_pitch;
+    let offset = source_length as f32 / 2.0 - (target_length as f32 / 2.0) * scale;
+    (0..target_length)
+        .map(|index| {
+            let start = offset + index as f32 * scale;
+            let end = start + scale;
+            let first = start.floor().max(0.0) as u32;
+            let last = std::cmp::min(end.ceil().max(0.0) as u32, source_length);
+            (first..last)
+                .filter_map(|source| {
+                    let overlap = end.min(source as f32 + 1.0) - start.max(source as f32);
+                    if overlap > 0.0 {
+                        Some((source, overlap / scale))
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Position of a source pixel centre on the target grid, along one axis.
+fn to_target(
+    source: u32,
+    source_length: u32,
+    source_pitch: f32,
+    target_length: u32,
+    target_pitch: f32,
+) -> f32 {
+    (source as f32 + 0.5 - source_length as f32 / 2.0) * source_pitch / target_pitch
+        + target_length as f32 / 2.0
+}
+
+/// Resamples `image` with pixels of `source_pitch` onto a `width` x `height` grid with pixels of
+/// `target_pitch`, preserving physical size. Also returns the number of lit source pixels that
+/// fell outside of the target grid.
+pub fn resample(
+    image: &GrayImage,
+    source_pitch: f32,
+    width: u32,
+    height: u32,
+    target_pitch: f32,
+) -> (GrayImage, usize) {
+    let x_weights = axis_weights(image.width(), source_pitch, width, target_pitch);
+    let y_weights = axis_weights(image.height(), source_pitch, height, target_pitch);
+    let output = GrayImage::from_fn(width, height, |x, y| {
+        let mut sum = 0.0;
+        for (sy, wy) in y_weights[y as usize].iter() {
+            for (sx, wx) in x_weights[x as usize].iter() {
+                sum += image.get_pixel(*sx, *sy).0[0] as f32 * wx * wy;
+            }
+        }
+        image::Luma([sum.round().min(255.0) as u8])
+    });
+    let clipped = image
+        .enumerate_pixels()
+        .filter(|(x, y, pixel)| {
+            if pixel.0[0] == 0 {
+                return false;
+            }
+            let tx = to_target(*x, image.width(), source_pitch, width, target_pitch);
+            let ty = to_target(*y, image.height(), source_pitch, height, target_pitch);
+            tx < 0.0 || ty < 0.0 || tx >= width as f32 || ty >= height as f32
+        })
+        .count();
+    (output, clipped)
+}
+
+#[test]
+fn test_resample() {
+    // Halving the resolution averages 2x2 blocks
+    let image = GrayImage::from_raw(4, 2, vec![255, 255, 0, 255, 255, 255, 0, 0]).unwrap();
+    let (output, clipped) = resample(&image, 1.0, 2, 1, 2.0);
+    assert_eq!(output.into_raw(), vec![255, 64]);
+    assert_eq!(clipped, 0);
+    // Same pitch onto a smaller grid crops around the centre
+    let (output, clipped) = resample(&image, 1.0, 2, 2, 1.0);
+    assert_eq!(output.into_raw(), vec![255, 0, 255, 0]);
+    assert_eq!(clipped, 3);
+}
@@ -0,0 +1,397 @@
+use crate::raster::font::render_text;
+use image::{Rgb, RgbImage};
+use std::io::Write;
+
+/*
+ * Minimal table output for analysis reports. Hand-rolled rather than pulling in serde, as the
+ * reports are flat lists of records.
+ */
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Int(i64),
+    Float(f64),
+    Text(String),
+}
+
+impl From<usize> for Value {
+    fn from(v: usize) -> Value {
+        Value::Int(v as i64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(v: u32) -> Value {
+        Value::Int(v as i64)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(v: f32) -> Value {
+        Value::Float(v as f64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(v: f64) -> Value {
+        Value::Float(v)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(v: &str) -> Value {
+        Value::Text(v.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(v: String) -> Value {
+        Value::Text(v)
+    }
+}
+
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+fn escape_csv(text: &str) -> String {
+    if text.contains(&[',', '"', '\n'][..]) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn escape_json(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len() + 2);
+    escaped.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+impl Table {
+    pub fn new(columns: Vec<&'static str>) -> Table {
+        Table {
+            columns,
+            rows: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, row: Vec<Value>) {
+        assert_eq!(row.len(), self.columns.len());
+        self.rows.push(row);
+    }
+
+    pub fn write_csv<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "{}", self.columns.join(","))?;
+        for row in self.rows.iter() {
+            let fields: Vec<String> = row
+                .iter()
+                .map(|value| match value {
+                    Value::Int(v) => v.to_string(),
+                    Value::Float(v) => format!("{:.4}", v),
+                    Value::Text(v) => escape_csv(v),
+                })
+                .collect();
+            writeln!(w, "{}", fields.join(","))?;
+        }
+        Ok(())
+    }
+
+    pub fn write_json<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(w, "[")?;
+        for (index, row) in self.rows.iter().enumerate() {
+            let fields: Vec<String> = self
+                .columns
+                .iter()
+                .zip(row.iter())
+                .map(|(column, value)| {
+                    let value = match value {
+                        Value::Int(v) => v.to_string(),
+                        Value::Float(v) if v.is_finite() => format!("{:.4}", v),
+                        Value::Float(_) => "null".to_string(),
+                        Value::Text(v) => escape_json(v),
+                    };
+                    format!("{}: {}", escape_json(column), value)
+                })
+                .collect();
+            let separator = if index + 1 < self.rows.len() { "," } else { "" };
+            writeln!(w, "  {{{}}}{}", fields.join(", "), separator)?;
+        }
+        writeln!(w, "]")
+    }
+
+    /// Writes as JSON or CSV depending on the file extension.
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => self.write_json(&mut file),
+            _ => self.write_csv(&mut file),
+        }
+    }
+}
+
+/*
+ * Charts are drawn as a stack of panels sharing the x axis, one per series, each scaled to its
+ * own range so that series with different units can be compared by shape.
+ */
+
+const CHART_WIDTH: u32 = 800;
+const CHART_MARGIN: u32 = 10; // Left and right of the panels
+const TITLE_HEIGHT: u32 = 20; // Above every panel
+const PANEL_HEIGHT: u32 = 140;
+const AXIS_HEIGHT: u32 = 24; // Below the last panel
+const SERIES_COLOURS: [[u8; 3]; 4] = [
+    [31, 119, 180],
+    [214, 39, 40],
+    [44, 160, 44],
+    [148, 103, 189],
+];
+
+pub struct Series {
+    pub name: String,
+    pub values: Vec<f32>,
+}
+
+pub struct Chart {
+    pub x_label: String,
+    pub x_values: Vec<f32>,
+    pub series: Vec<Series>,
+}
+
+/// Range to plot values in, always including zero and never empty.
+fn value_range(values: &[f32]) -> (f32, f32) {
+    let min = values.iter().cloned().fold(0.0, f32::min);
+    let max = values.iter().cloned().fold(0.0, f32::max);
+    if max - min > 0.0 {
+        (min, max)
+    } else {
+        (min, min + 1.0)
+    }
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+impl Chart {
+    pub fn height(&self) -> u32 {
+        self.series.len() as u32 * (TITLE_HEIGHT + PANEL_HEIGHT) + AXIS_HEIGHT
+    }
+
+    fn panel_top(index: usize) -> f32 {
+        (index as u32 * (TITLE_HEIGHT + PANEL_HEIGHT) + TITLE_HEIGHT) as f32
+    }
+
+    fn title(series: &Series) -> String {
+        let (min, max) = value_range(&series.values);
+        format!("{}: {:.2} to {:.2}", series.name, min, max)
+    }
+
+    fn axis_title(&self) -> String {
+        let first = self.x_values.first().cloned().unwrap_or(0.0);
+        let last = self.x_values.last().cloned().unwrap_or(0.0);
+        format!("{}: {:.2} to {:.2}", self.x_label, first, last)
+    }
+
+    /// Maps a value of the series in panel `index` to its height in the chart.
+    fn to_y(index: usize, range: (f32, f32), value: f32) -> f32 {
+        Chart::panel_top(index) + PANEL_HEIGHT as f32 * (range.1 - value) / (range.1 - range.0)
+    }
+
+    /// Points of the series in panel `index`, in chart coordinates.
+    fn points(&self, index: usize) -> Vec<(f32, f32)> {
+        let series = &self.series[index];
+        let range = value_range(&series.values);
+        let x_first = self.x_values.first().cloned().unwrap_or(0.0);
+        let x_last = self.x_values.last().cloned().unwrap_or(0.0);
+        let x_span = if x_last > x_first {
+            x_last - x_first
+        } else {
+            1.0
+        };
+        let plot_width = (CHART_WIDTH - 2 * CHART_MARGIN) as f32;
+        self.x_values
+            .iter()
+            .zip(series.values.iter())
+            .map(|(x, value)| {
+                (
+                    CHART_MARGIN as f32 + plot_width * (x - x_first) / x_span,
+                    Chart::to_y(index, range, *value),
+                )
+            })
+            .collect()
+    }
+
+    pub fn write_svg<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        writeln!(
+            w,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" \
+             font-family=\"sans-serif\" font-size=\"12\">",
+            CHART_WIDTH,
+            self.height()
+        )?;
+        writeln!(w, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>")?;
+        for (index, series) in self.series.iter().enumerate() {
+            let top = Chart::panel_top(index);
+            let range = value_range(&series.values);
+            let colour = SERIES_COLOURS[index % SERIES_COLOURS.len()];
+            writeln!(
+                w,
+                "<text x=\"{}\" y=\"{}\">{}</text>",
+                CHART_MARGIN,
+                top - 6.0,
+                escape_xml(&Chart::title(series))
+            )?;
+            writeln!(
+                w,
+                "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"none\" \
+                 stroke=\"#888\"/>",
+                CHART_MARGIN,
+                top,
+                CHART_WIDTH - 2 * CHART_MARGIN,
+                PANEL_HEIGHT
+            )?;
+            if range.0 < 0.0 {
+                let zero = Chart::to_y(index, range, 0.0);
+                writeln!(
+                    w,
+                    "<line x1=\"{}\" y1=\"{:.1}\" x2=\"{}\" y2=\"{:.1}\" stroke=\"#ccc\"/>",
+                    CHART_MARGIN,
+                    zero,
+                    CHART_WIDTH - CHART_MARGIN,
+                    zero
+                )?;
+            }
+            let points: Vec<String> = self
+                .points(index)
+                .iter()
+                .map(|(x, y)| format!("{:.1},{:.1}", x, y))
+                .collect();
+            writeln!(
+                w,
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"rgb({},{},{})\"/>",
+                points.join(" "),
+                colour[0],
+                colour[1],
+                colour[2]
+            )?;
+        }
+        writeln!(
+            w,
+            "<text x=\"{}\" y=\"{}\">{}</text>",
+            CHART_MARGIN,
+            self.height() - 6,
+            escape_xml(&self.axis_title())
+        )?;
+        writeln!(w, "</svg>")
+    }
+
+    /// Draws the chart as an image, with titles in the built-in font.
+    pub fn render(&self) -> RgbImage {
+        let mut image = RgbImage::from_pixel(CHART_WIDTH, self.height(), Rgb([255, 255, 255]));
+        for (index, series) in self.series.iter().enumerate() {
+            let top = Chart::panel_top(index) as u32;
+            let range = value_range(&series.values);
+            let colour = Rgb(SERIES_COLOURS[index % SERIES_COLOURS.len()]);
+            draw_text(&mut image, &Chart::title(series), CHART_MARGIN, top - 17);
+            let (left, right, bottom) =
+                (CHART_MARGIN, CHART_WIDTH - CHART_MARGIN, top + PANEL_HEIGHT);
+            let frame = Rgb([136, 136, 136]);
+            draw_line(
+                &mut image,
+                (left as f32, top as f32),
+                (right as f32, top as f32),
+                frame,
+            );
+            draw_line(
+                &mut image,
+                (left as f32, bottom as f32),
+                (right as f32, bottom as f32),
+                frame,
+            );
+            draw_line(
+                &mut image,
+                (left as f32, top as f32),
+                (left as f32, bottom as f32),
+                frame,
+            );
+            draw_line(
+                &mut image,
+                (right as f32, top as f32),
+                (right as f32, bottom as f32),
+                frame,
+            );
+            if range.0 < 0.0 {
+                let zero = Chart::to_y(index, range, 0.0);
+                draw_line(
+                    &mut image,
+                    (left as f32, zero),
+                    (right as f32, zero),
+                    Rgb([204, 204, 204]),
+                );
+            }
+            for pair in self.points(index).windows(2) {
+                draw_line(&mut image, pair[0], pair[1], colour);
+            }
+        }
+        draw_text(
+            &mut image,
+            &self.axis_title(),
+            CHART_MARGIN,
+            self.height() - 19,
+        );
+        image
+    }
+
+    /// Writes as PNG or SVG depending on the file extension.
+    pub fn save(&self, path: &std::path::Path) -> std::io::Result<()> {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("png") => self.render().save(path),
+            _ => {
+                let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+                self.write_svg(&mut file)
+            }
+        }
+    }
+}
+
+/// Draws a one pixel wide line, clipped to the image.
+fn draw_line(image: &mut RgbImage, from: (f32, f32), to: (f32, f32), colour: Rgb<u8>) {
+    let steps = f32::max((to.0 - from.0).abs(), (to.1 - from.1).abs())
+        .ceil()
+        .max(1.0) as u32;
+    for step in 0..=steps {
+        let t = step as f32 / steps as f32;
+        let x = (from.0 + (to.0 - from.0) * t).round();
+        let y = (from.1 + (to.1 - from.1) * t).round();
+        if x >= 0.0 && y >= 0.0 && (x as u32) < image.width() && (y as u32) < image.height() {
+            image.put_pixel(x as u32, y as u32, colour);
+        }
+    }
+}
+
+/// Draws text in black at twice the font size, with its top-left corner at (x, y).
+fn draw_text(image: &mut RgbImage, text: &str, x: u32, y: u32) {
+    let text = render_text(text);
+    for ty in 0..text.height * 2 {
+        for tx in 0..text.width * 2 {
+            let (px, py) = (x + tx, y + ty);
+            if text.get(tx / 2, ty / 2) && px < image.width() && py < image.height() {
+                image.put_pixel(px, py, Rgb([0, 0, 0]));
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile, PwsLayer};
+use crate::raster::Bitmap;
+use crate::transform::zresample::{exposure_factor, merge_layers};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Consecutive layers are merged into one as long as the cross-section hardly changes. The
+ * difference between neighbouring layers is the number of pixels lit in only one of them,
+ * relative to the larger lit area. Differences are added up over a merged group, which bounds
+ * how far its last layer can be from its first. Merged layers take the union of their layers so
+ * no features are lost. Bottom layers are never merged.
+ */
+
+#[derive(Clone, Copy, Debug)]
+pub struct AdaptiveLayers {
+    pub max_layer_height: f32,  // in mm
+    pub max_difference: f32,    // Fraction of the lit area allowed to change within a layer
+    pub penetration_depth: f32, // Resin penetration depth Dp, in mm
+}
+
+fn difference(a: &Bitmap, b: &Bitmap) -> f32 {
+    let changed = a
+        .data
+        .iter()
+        .zip(b.data.iter())
+        .filter(|(a, b)| a != b)
+        .count();
+    let area = std::cmp::max(std::cmp::max(a.count(), b.count()), 1);
+    changed as f32 / area as f32
+}
+
+/// Merges similar layers, and enables per-layer parameters. Returns the new number of layers.
+pub fn adaptive_layer_heights<F>(
+    file: &mut PwsFile,
+    settings: &AdaptiveLayers,
+    on_layer: F,
+) -> usize
+where
+    F: Fn(usize) + Sync,
+{
+    file.enable_individual_parameters();
+    let num_layers = file.layers.len();
+    let file_ref: &PwsFile = file;
+    // Difference between every layer and the next
+    let differences: Vec<f32> = (0..num_layers.saturating_sub(1))
+        .into_par_iter()
+        .map(|index| {
+            let a = Bitmap::from_image(&file_ref.decode_layer(index));
+            let b = Bitmap::from_image(&file_ref.decode_layer(index + 1));
+            on_layer(index);
+            difference(&a, &b)
+        })
+        .collect();
+
+    let first_merged = std::cmp::min(file.header.num_bottom_layers.round() as usize, num_layers);
+    let mut groups: Vec<(usize, usize)> = (0..first_merged).map(|index| (index, index)).collect();
+    let mut start = first_merged;
+    while start < num_layers {
+        let mut end = start;
+        let mut height = file.layer_thickness(start);
+        let mut changed = 0.0;
+        while end + 1 < num_layers {
+            let next_height = height + file.layer_thickness(end + 1);
+            let next_changed = changed + differences[end];
+            if next_height > settings.max_layer_height + 1e-6
+                || next_changed > settings.max_difference
+            {
+                break;
+            }
+            end += 1;
+            height = next_height;
+            changed = next_changed;
+        }
+        groups.push((start, end));
+        start = end + 1;
+    }
+
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let file_ref: &PwsFile = file;
+    let layers: Vec<PwsLayer> = groups
+        .into_par_iter()
+        .map(|(start, end)| {
+            let first = &file_ref.layers[start];
+            let data = if start == end {
+                CompressedBitstream(first.data.0.clone())
+            } else {
+                let images = (start..=end)
+                    .map(|index| file_ref.decode_layer(index))
+                    .collect();
+                CompressedBitstream::from_image(&merge_layers(images), bits_per_pixel)
+            };
+            let old_height = file_ref.layer_thickness(start);
+            let height: f32 = (start..=end)
+                .map(|index| file_ref.layer_thickness(index))
+                .sum();
+            PwsLayer {
+                lift_distance: first.lift_distance,
+                lift_speed: first.lift_speed,
+                exposure_time: first.exposure_time
+                    * exposure_factor(old_height, height, settings.penetration_depth),
+                layer_height: height,
+                data,
+            }
+        })
+        .collect();
+    file.layers = layers;
+    file.header.volume = compute_volume(file);
+    file.layers.len()
+}
+
+#[test]
+fn test_adaptive_layer_heights() {
+    use crate::generate::test_job;
+    use image::{GrayImage, Luma};
+
+    let image = GrayImage::from_pixel(8, 8, Luma([255]));
+    let mut file = test_job(8, 8, &vec![image; 4]);
+    for layer in file.layers.iter_mut() {
+        layer.exposure_time = 0.0;
+        layer.layer_height = 0.0;
+        layer.lift_distance = 0.0;
+        layer.lift_speed = 0.0;
+    }
+    // Unused per-layer values are replaced by the header ones
+    file.header.use_individual_parameters = false;
+    file.header.num_bottom_layers = 1.0;
+    let settings = AdaptiveLayers {
+        max_layer_height: 0.15,
+        max_difference: 0.1,
+        penetration_depth: 0.1,
+    };
+    assert_eq!(adaptive_layer_heights(&mut file, &settings, |_| ()), 2);
+    assert!(file.header.use_individual_parameters);
+    let bottom = &file.layers[0];
+    assert_eq!(bottom.exposure_time, file.header.bottom_exposure_time);
+    assert_eq!(bottom.layer_height, 0.05);
+    let merged = &file.layers[1];
+    assert!((merged.layer_height - 0.15).abs() < 1e-6);
+    assert!(merged.exposure_time > file.header.exposure_time);
+    assert_eq!(merged.lift_distance, file.header.lift_distance);
+    assert_eq!(merged.lift_speed, file.header.lift_speed);
+}
@@ -0,0 +1,26 @@
+use crate::formats::pws::data::{supported_bits_per_pixel, CompressedBitstream, PwsFile};
+use crate::raster::antialias::{synthesize_antialiasing, Antialias};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Adds anti-aliasing to the black and white layers of a job, re-encoding all layers with at
+/// least `bits_per_pixel` levels, see `supported_bits_per_pixel`.
+pub fn antialias_job<F>(file: &mut PwsFile, method: Antialias, bits_per_pixel: u32, on_layer: F)
+where
+    F: Fn(usize) + Sync,
+{
+    let bits_per_pixel =
+        supported_bits_per_pixel(std::cmp::max(bits_per_pixel, file.header.bits_per_pixel));
+    let file_ref: &PwsFile = file;
+    let smoothed: Vec<CompressedBitstream> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let image = synthesize_antialiasing(&file_ref.decode_layer(index), method);
+            on_layer(index);
+            CompressedBitstream::from_image(&image, bits_per_pixel as usize)
+        })
+        .collect();
+    for (layer, data) in file.layers.iter_mut().zip(smoothed) {
+        layer.data = data;
+    }
+    file.header.bits_per_pixel = bits_per_pixel;
+}
@@ -0,0 +1,112 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::components::BoundingBox;
+use crate::raster::copy_region;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+#[derive(Clone, Copy, Debug)]
+pub struct ArrayLayout {
+    pub count: usize,
+    pub columns: Option<usize>, // Defaults to a roughly square grid
+    pub spacing: f32,           // Gap between copies, in mm
+}
+
+/// Top-left pixel positions of every copy of a part occupying `bounds`, centred on the plate.
+pub fn plan_array(
+    file: &PwsFile,
+    bounds: &BoundingBox,
+    layout: &ArrayLayout,
+) -> Result<Vec<(u32, u32)>, String> {
+    if layout.count == 0 {
+        return Err("Need at least one copy".to_string());
+    }
+    let columns = layout
+        .columns
+        .unwrap_or_else(|| (layout.count as f32).sqrt().ceil() as usize)
+        .max(1)
+        .min(layout.count);
+    let rows = layout.count.div_ceil(columns);
+    let gap = (layout.spacing / file.header.pixel_size_mm()).round() as u32;
+    let total_width = columns as u32 * bounds.width() + (columns as u32 - 1) * gap;
+    let total_height = rows as u32 * bounds.height() + (rows as u32 - 1) * gap;
+    if total_width > file.header.width || total_height > file.header.height {
+        return Err(format!(
+            "{} x {} copies need {:.2} x {:.2} mm, but the plate is only {:.2} x {:.2} mm",
+            columns,
+            rows,
+            total_width as f32 * file.header.pixel_size_mm(),
+            total_height as f32 * file.header.pixel_size_mm(),
+            file.header.width as f32 * file.header.pixel_size_mm(),
+            file.header.height as f32 * file.header.pixel_size_mm(),
+        ));
+    }
+    let left = (file.header.width - total_width) / 2;
+    let top = (file.header.height - total_height) / 2;
+    Ok((0..layout.count)
+        .map(|index| {
+            let (column, row) = ((index % columns) as u32, (index / columns) as u32);
+            (
+                left + column * (bounds.width() + gap),
+                top + row * (bounds.height() + gap),
+            )
+        })
+        .collect())
+}
+
+/// Replaces every layer by copies of the part at the given positions, and updates the volume.
+pub fn array_copies<F>(
+    file: &mut PwsFile,
+    bounds: &BoundingBox,
+    positions: &[(u32, u32)],
+    on_layer: F,
+) where
+    F: Fn(usize) + Sync,
+{
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let (width, height) = (file.header.width, file.header.height);
+    let file_ref: &PwsFile = file;
+    let arrayed: Vec<CompressedBitstream> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let image = file_ref.decode_layer(index);
+            let mut output = GrayImage::new(width, height);
+            for (x, y) in positions.iter() {
+                copy_region(&mut output, &image, bounds, *x, *y);
+            }
+            on_layer(index);
+            CompressedBitstream::from_image(&output, bits_per_pixel)
+        })
+        .collect();
+    for (layer, data) in file.layers.iter_mut().zip(arrayed) {
+        layer.data = data;
+    }
+    file.header.volume = compute_volume(file);
+}
+
+#[test]
+fn test_plan_array() {
+    use crate::generate::test_job;
+
+    let file = test_job(20, 10, &[]);
+    let bounds = BoundingBox {
+        min_x: 2,
+        min_y: 2,
+        max_x: 4,
+        max_y: 3,
+    };
+    // Three 3x2 copies with 1 mm gaps make a 2x2 grid of 7x5 pixels, centred on the plate
+    let layout = ArrayLayout {
+        count: 3,
+        columns: None,
+        spacing: 1.0,
+    };
+    let positions = plan_array(&file, &bounds, &layout).unwrap();
+    assert_eq!(positions, vec![(6, 2), (10, 2), (6, 5)]);
+    let layout = ArrayLayout {
+        count: 6,
+        columns: Some(6),
+        spacing: 1.0,
+    };
+    assert!(plan_array(&file, &bounds, &layout).is_err());
+}
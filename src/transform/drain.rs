@@ -0,0 +1,165 @@
+use crate::analysis::cavities::Cavity;
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::components::label_4_connected;
+use crate::raster::Bitmap;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Vertical cylinder to cut out of the part.
+#[derive(Clone, Debug)]
+pub struct DrainHole {
+    pub x: f32,        // in mm
+    pub y: f32,        // in mm
+    pub diameter: f32, // in mm
+    pub first_layer: usize,
+    pub last_layer: usize, // inclusive
+}
+
+impl DrainHole {
+    fn cut(&self, image: &mut GrayImage, pixel_size: f32) -> bool {
+        let radius = self.diameter / 2.0 / pixel_size;
+        let (cx, cy) = (self.x / pixel_size, self.y / pixel_size);
+        let min_x = (cx - radius).floor().max(0.0) as u32;
+        let min_y = (cy - radius).floor().max(0.0) as u32;
+        let max_x = std::cmp::min((cx + radius).ceil() as u32, image.width());
+        let max_y = std::cmp::min((cy + radius).ceil() as u32, image.height());
+        let mut changed = false;
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                let pixel = image.get_pixel_mut(x, y);
+                if dx * dx + dy * dy <= radius * radius && pixel.0[0] != 0 {
+                    pixel.0[0] = 0;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Places a hole at the bottom of every cavity, up into the cavity from the closest empty layer
+/// below it, or from the build plate.
+pub fn holes_for_cavities(file: &PwsFile, cavities: &[Cavity], diameter: f32) -> Vec<DrainHole> {
+    let pixel_size = file.header.pixel_size_mm();
+    cavities
+        .iter()
+        .filter_map(|cavity| {
+            // The centroid may fall outside of a concave cavity, move it to the closest pixel of
+            // the cavity. Other empty regions in its bounds may be outside air or other cavities.
+            let image = file.decode_layer(cavity.first_layer);
+            let labels = label_4_connected(&Bitmap::from_image(&image), false);
+            let cavity_label = labels.get(cavity.seed.0, cavity.seed.1)?;
+            let (cx, cy) = (cavity.bottom.0 / pixel_size, cavity.bottom.1 / pixel_size);
+            let bounds = &cavity.bounds;
+            let mut best = (cx, cy);
+            let mut best_distance = f32::INFINITY;
+            for y in bounds.min_y..=bounds.max_y {
+                for x in bounds.min_x..=bounds.max_x {
+                    let (dx, dy) = (x as f32 + 0.5 - cx, y as f32 + 0.5 - cy);
+                    if labels.get(x, y) == Some(cavity_label) && dx * dx + dy * dy < best_distance {
+                        best = (x as f32 + 0.5, y as f32 + 0.5);
+                        best_distance = dx * dx + dy * dy;
+                    }
+                }
+            }
+            // Only drill through the part below the cavity, not through anything further down
+            let (x, y) = (best.0 as u32, best.1 as u32);
+            let first_layer = (0..cavity.first_layer)
+                .rev()
+                .find(|index| file.decode_layer(*index).get_pixel(x, y).0[0] == 0)
+                .map_or(0, |index| index + 1);
+            // Reach far enough into the cavity for the full diameter to open up.
+            let overlap = file.layer_at_height(diameter / 2.0) + 1;
+            Some(DrainHole {
+                x: best.0 * pixel_size,
+                y: best.1 * pixel_size,
+                diameter,
+                first_layer,
+                last_layer: std::cmp::min(cavity.first_layer + overlap, cavity.last_layer),
+            })
+        })
+        .collect()
+}
+
+/// Cuts the holes out of every layer they span, and updates the volume.
+pub fn punch_drain_holes<F>(file: &mut PwsFile, holes: &[DrainHole], on_layer: F)
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let file_ref: &PwsFile = file;
+    let punched: Vec<Option<CompressedBitstream>> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let mut changed = false;
+            let mut image = None;
+            for hole in holes {
+                if index < hole.first_layer || index > hole.last_layer {
+                    continue;
+                }
+                let image = image.get_or_insert_with(|| file_ref.decode_layer(index));
+                changed |= hole.cut(image, pixel_size);
+            }
+            on_layer(index);
+            match image {
+                Some(image) if changed => {
+                    Some(CompressedBitstream::from_image(&image, bits_per_pixel))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    for (layer, data) in file.layers.iter_mut().zip(punched) {
+        if let Some(data) = data {
+            layer.data = data;
+        }
+    }
+    file.header.volume = compute_volume(file);
+}
+
+#[test]
+fn test_holes_for_cavities() {
+    use crate::generate::test_job;
+    use crate::raster::components::BoundingBox;
+    use image::Luma;
+
+    // A one pixel cavity at (2, 2), and a notch of outside air closer to the given centroid
+    let block = GrayImage::from_fn(9, 5, |x, y| {
+        Luma([if (1..8).contains(&x) && (1..4).contains(&y) {
+            255
+        } else {
+            0
+        }])
+    });
+    let mut image = block.clone();
+    for (x, y) in [(2, 2), (5, 1), (5, 2)].iter() {
+        image.put_pixel(*x, *y, Luma([0]));
+    }
+    // Below a floor of one layer, with an unrelated gap under the cavity
+    let mut gap = block.clone();
+    gap.put_pixel(2, 2, Luma([0]));
+    let file = test_job(9, 5, &[block.clone(), gap, block, image]);
+    let cavity = |seed| Cavity {
+        first_layer: 3,
+        last_layer: 3,
+        voxels: 1,
+        volume: 0.05,
+        bounds: BoundingBox {
+            min_x: 1,
+            min_y: 1,
+            max_x: 7,
+            max_y: 3,
+        },
+        bottom: (5.5, 2.5),
+        seed,
+        open_top: false,
+    };
+    let holes = holes_for_cavities(&file, &[cavity((2, 2))], 0.5);
+    assert_eq!((holes[0].x, holes[0].y), (2.5, 2.5));
+    assert_eq!((holes[0].first_layer, holes[0].last_layer), (2, 3));
+    // A seed on a lit pixel does not belong to any cavity
+    assert!(holes_for_cavities(&file, &[cavity((1, 1))], 0.5).is_empty());
+}
@@ -0,0 +1,68 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::morphology::erode;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Shrinks the first layers to counter over-curing from the longer bottom exposure.
+#[derive(Clone, Debug)]
+pub struct ElephantFoot {
+    pub distance: f32, // in mm
+    pub num_layers: usize,
+    pub taper: bool, // Linearly reduce the distance to zero over num_layers
+}
+
+impl ElephantFoot {
+    /// Erosion distance in mm for a given layer.
+    pub fn distance_for_layer(&self, index: usize) -> f32 {
+        if index >= self.num_layers {
+            0.0
+        } else if self.taper {
+            self.distance * (self.num_layers - index) as f32 / self.num_layers as f32
+        } else {
+            self.distance
+        }
+    }
+
+    /// Returns the compensated image, or None if the layer is left as-is.
+    pub fn apply_to_layer(
+        &self,
+        index: usize,
+        image: &GrayImage,
+        pixel_size: f32,
+    ) -> Option<GrayImage> {
+        let radius = self.distance_for_layer(index) / pixel_size;
+        if radius > 0.0 {
+            Some(erode(image, radius))
+        } else {
+            None
+        }
+    }
+}
+
+pub fn compensate_elephant_foot<F>(file: &mut PwsFile, settings: &ElephantFoot, on_layer: F)
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let num_layers = std::cmp::min(settings.num_layers, file.layers.len());
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let file_ref: &PwsFile = file;
+    let compensated: Vec<Option<CompressedBitstream>> = (0..num_layers)
+        .into_par_iter()
+        .map(|index| {
+            let image = file_ref.decode_layer(index);
+            let compensated = settings
+                .apply_to_layer(index, &image, pixel_size)
+                .map(|image| CompressedBitstream::from_image(&image, bits_per_pixel));
+            on_layer(index);
+            compensated
+        })
+        .collect();
+    for (layer, data) in file.layers.iter_mut().zip(compensated) {
+        if let Some(data) = data {
+            layer.data = data;
+        }
+    }
+    file.header.volume = compute_volume(file);
+}
@@ -0,0 +1,167 @@
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use image::{imageops, ImageBuffer, Pixel, Rgb, RgbImage};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Geometry {
+    MirrorX,  // x becomes width - 1 - x
+    MirrorY,  // y becomes height - 1 - y
+    Rotate90, // Clockwise
+    Rotate180,
+    Rotate270,
+    Translate(i32, i32), // in pixels, positive moves right and down
+}
+
+impl Geometry {
+    pub fn swaps_dimensions(&self) -> bool {
+        matches!(self, Geometry::Rotate90 | Geometry::Rotate270)
+    }
+
+    /// Applies the operation to an image. Pixels moved off the image are dropped, and the
+    /// number of those that were not black is returned along with the new image.
+    pub fn apply<P>(
+        &self,
+        image: &ImageBuffer<P, Vec<P::Subpixel>>,
+    ) -> (ImageBuffer<P, Vec<P::Subpixel>>, usize)
+    where
+        P: Pixel + 'static,
+        P::Subpixel: 'static + Default + PartialEq,
+    {
+        match *self {
+            Geometry::MirrorX => (imageops::flip_horizontal(image), 0),
+            Geometry::MirrorY => (imageops::flip_vertical(image), 0),
+            Geometry::Rotate90 => (imageops::rotate90(image), 0),
+            Geometry::Rotate180 => (imageops::rotate180(image), 0),
+            Geometry::Rotate270 => (imageops::rotate270(image), 0),
+            Geometry::Translate(dx, dy) => {
+                let (width, height) = image.dimensions();
+                let mut output = ImageBuffer::new(width, height);
+                let mut clipped = 0;
+                for (x, y, pixel) in image.enumerate_pixels() {
+                    if pixel
+                        .channels()
+                        .iter()
+                        .all(|c| *c == P::Subpixel::default())
+                    {
+                        continue;
+                    }
+                    let (nx, ny) = (x as i64 + dx as i64, y as i64 + dy as i64);
+                    if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                        clipped += 1;
+                    } else {
+                        output.put_pixel(nx as u32, ny as u32, *pixel);
+                    }
+                }
+                (output, clipped)
+            }
+        }
+    }
+
+    /// Applies the operation to a preview image, keeping its size so the printer still accepts
+    /// it. Translations are scaled from layer pixels to preview pixels.
+    pub fn apply_to_preview(
+        &self,
+        preview: &RgbImage,
+        layer_width: u32,
+        layer_height: u32,
+    ) -> RgbImage {
+        let (width, height) = preview.dimensions();
+        let operation = match *self {
+            Geometry::Translate(dx, dy) => Geometry::Translate(
+                (dx as i64 * width as i64 / std::cmp::max(layer_width, 1) as i64) as i32,
+                (dy as i64 * height as i64 / std::cmp::max(layer_height, 1) as i64) as i32,
+            ),
+            operation => operation,
+        };
+        let (transformed, _) = operation.apply(preview);
+        if transformed.dimensions() == (width, height) {
+            return transformed;
+        }
+        fit_image(&transformed, width, height)
+    }
+}
+
+/// Scales an image to fit within the given size, centred on a black background.
+pub fn fit_image(image: &RgbImage, width: u32, height: u32) -> RgbImage {
+    let scale = f32::min(
+        width as f32 / image.width() as f32,
+        height as f32 / image.height() as f32,
+    );
+    let scaled_width = std::cmp::max(1, (image.width() as f32 * scale) as u32);
+    let scaled_height = std::cmp::max(1, (image.height() as f32 * scale) as u32);
+    let scaled = imageops::resize(
+        image,
+        scaled_width,
+        scaled_height,
+        imageops::FilterType::Triangle,
+    );
+    let mut output = RgbImage::from_pixel(width, height, Rgb([0, 0, 0]));
+    imageops::overlay(
+        &mut output,
+        &scaled,
+        (width - scaled.width()) / 2,
+        (height - scaled.height()) / 2,
+    );
+    output
+}
+
+/// Applies the operation to every layer and the preview. Returns the indices of layers that lost
+/// lit pixels off the edge of the plate, with the number of pixels lost.
+pub fn transform_geometry<F>(
+    file: &mut PwsFile,
+    operation: Geometry,
+    on_layer: F,
+) -> Vec<(usize, usize)>
+where
+    F: Fn(usize) + Sync,
+{
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let file_ref: &PwsFile = file;
+    let transformed: Vec<(CompressedBitstream, usize)> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let (image, clipped) = operation.apply(&file_ref.decode_layer(index));
+            on_layer(index);
+            (
+                CompressedBitstream::from_image(&image, bits_per_pixel),
+                clipped,
+            )
+        })
+        .collect();
+    file.preview = operation.apply_to_preview(&file.preview, file.header.width, file.header.height);
+    if operation.swaps_dimensions() {
+        std::mem::swap(&mut file.header.width, &mut file.header.height);
+    }
+    let mut clipped_layers = Vec::new();
+    for (index, (layer, (data, clipped))) in file.layers.iter_mut().zip(transformed).enumerate() {
+        layer.data = data;
+        if clipped > 0 {
+            clipped_layers.push((index, clipped));
+        }
+    }
+    clipped_layers
+}
+
+#[test]
+fn test_transform_geometry() {
+    use crate::generate::test_job;
+    use image::GrayImage;
+
+    let image = GrayImage::from_raw(4, 2, vec![255, 0, 0, 0, 255, 255, 0, 0]).unwrap();
+    let mut file = test_job(4, 2, &[image]);
+    // Clockwise rotation swaps the plate dimensions and loses nothing
+    let clipped = transform_geometry(&mut file, Geometry::Rotate90, |_| {});
+    assert!(clipped.is_empty());
+    assert_eq!((file.header.width, file.header.height), (2, 4));
+    assert_eq!(
+        file.decode_layer(0).into_raw(),
+        vec![255, 255, 255, 0, 0, 0, 0, 0]
+    );
+    // Moving down by three pixels pushes the second row off the plate
+    let clipped = transform_geometry(&mut file, Geometry::Translate(0, 3), |_| {});
+    assert_eq!(clipped, vec![(0, 1)]);
+    assert_eq!(
+        file.decode_layer(0).into_raw(),
+        vec![0, 0, 0, 0, 0, 0, 255, 255]
+    );
+}
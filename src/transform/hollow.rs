@@ -0,0 +1,236 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::distance::squared_distance_to;
+use crate::raster::Bitmap;
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use rayon::slice::ParallelSliceMut;
+use std::collections::VecDeque;
+
+/*
+ * The 3D distance from a pixel to the nearest empty voxel is the minimum over nearby layers of
+ * that layer's 2D distance combined with the Z distance between the layers. Only layers within
+ * the wall thickness can matter, so a sliding window of 2D distance maps is kept in memory. The
+ * space below the first and above the last layer counts as empty, so the part stays closed.
+ */
+
+// Squared distances are stored in pixels^2 as u16 to keep the window small.
+const MAX_SQUARED_DISTANCE: u16 = u16::MAX;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Infill {
+    None,
+    Grid { spacing: f32, thickness: f32 },      // in mm
+    Honeycomb { spacing: f32, thickness: f32 }, // in mm, spacing between opposite walls
+}
+
+impl Infill {
+    /// Whether the given point on the plate (in mm) is part of the infill.
+    pub fn contains(&self, x: f32, y: f32) -> bool {
+        match *self {
+            Infill::None => false,
+            Infill::Grid { spacing, thickness } => {
+                let on_line = |v: f32| (v + thickness / 2.0).rem_euclid(spacing) < thickness;
+                on_line(x) || on_line(y)
+            }
+            Infill::Honeycomb { spacing, thickness } => {
+                // Pointy-top hexagons, centres on a lattice spanned by (s, 0) and
+                // (s/2, s*sqrt(3)/2)
+                let sqrt3 = 3f32.sqrt();
+                let row = (y / (spacing * sqrt3 / 2.0)).floor();
+                let column = (x / spacing - row / 2.0).floor();
+                let mut nearest = f32::INFINITY;
+                for (dr, dc) in &[(0.0, 0.0), (0.0, 1.0), (1.0, 0.0), (1.0, 1.0)] {
+                    let (r, c) = (row + dr, column + dc);
+                    let dx = x - (c + r / 2.0) * spacing;
+                    let dy = y - r * spacing * sqrt3 / 2.0;
+                    // Hexagonal norm: distance to the furthest edge normal
+                    let norm = dx
+                        .abs()
+                        .max((dx * 0.5 + dy * sqrt3 / 2.0).abs())
+                        .max((dx * 0.5 - dy * sqrt3 / 2.0).abs());
+                    nearest = nearest.min(norm);
+                }
+                nearest > (spacing - thickness) / 2.0
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Hollow {
+    pub wall_thickness: f32, // in mm
+    pub infill: Infill,
+}
+
+fn layer_distances(file: &PwsFile, index: usize) -> Vec<u16> {
+    let bitmap = Bitmap::from_image(&file.decode_layer(index));
+    squared_distance_to(&bitmap, false)
+        .into_iter()
+        .map(|d| d.min(MAX_SQUARED_DISTANCE as f32) as u16)
+        .collect()
+}
+
+/// Removes everything further than the wall thickness from the surface, and updates the volume.
+pub fn hollow<F>(file: &mut PwsFile, settings: &Hollow, on_layer: F) -> Result<(), String>
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let wall = settings.wall_thickness / pixel_size; // in pixels
+    if !(settings.wall_thickness > 0.0 && settings.wall_thickness.is_finite()) {
+        return Err(format!(
+            "Wall thickness of {} mm should be positive",
+            settings.wall_thickness
+        ));
+    }
+    if wall * wall >= MAX_SQUARED_DISTANCE as f32 {
+        return Err(format!(
+            "Wall thickness of {} mm is too large",
+            settings.wall_thickness
+        ));
+    }
+    let wall_squared = wall * wall;
+    let num_layers = file.layers.len();
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let width = file.header.width as usize;
+
+    // Z position of the bottom of every layer, in pixels
+    let mut z = Vec::with_capacity(num_layers + 1);
+    let mut current = 0.0;
+    for index in 0..num_layers {
+        z.push(current);
+        current += file.layer_thickness(index) / pixel_size;
+    }
+    z.push(current);
+
+    let mut window: VecDeque<Vec<u16>> = VecDeque::new();
+    let mut window_start = 0;
+    let mut hollowed: Vec<Option<CompressedBitstream>> = Vec::with_capacity(num_layers);
+    for index in 0..num_layers {
+        while z[index] - z[window_start] > wall {
+            window.pop_front();
+            window_start += 1;
+        }
+        let window_end = (index..num_layers)
+            .take_while(|other| z[*other] - z[index] <= wall)
+            .last()
+            .unwrap();
+        let first_missing = window_start + window.len();
+        let file_ref: &PwsFile = file;
+        let loaded: Vec<Vec<u16>> = (first_missing..=window_end)
+            .into_par_iter()
+            .map(|other| layer_distances(file_ref, other))
+            .collect();
+        window.extend(loaded);
+
+        // Distance to the empty space below the first and above the last layer
+        let closed_bottom = z[index] - z[0] < wall;
+        let closed_top = z[num_layers] - z[index + 1] < wall;
+        if closed_bottom || closed_top {
+            hollowed.push(None);
+            on_layer(index);
+            continue;
+        }
+
+        let own = &window[index - window_start];
+        let others: Vec<(&Vec<u16>, f32)> = window
+            .iter()
+            .enumerate()
+            .filter(|(offset, _)| window_start + offset != index)
+            .map(|(offset, distances)| {
+                let dz = z[window_start + offset] - z[index];
+                (distances, dz * dz)
+            })
+            .collect();
+        let mut image = file.decode_layer(index);
+        let changed = image
+            .par_chunks_mut(width)
+            .enumerate()
+            .map(|(y, row)| {
+                let mut changed = false;
+                for (x, value) in row.iter_mut().enumerate() {
+                    let pixel_index = y * width + x;
+                    if *value == 0 || own[pixel_index] as f32 <= wall_squared {
+                        continue;
+                    }
+                    let is_wall = others.iter().any(|(distances, dz2)| {
+                        distances[pixel_index] as f32 + dz2 <= wall_squared
+                    });
+                    if !is_wall
+                        && !settings
+                            .infill
+                            .contains(x as f32 * pixel_size, y as f32 * pixel_size)
+                    {
+                        *value = 0;
+                        changed = true;
+                    }
+                }
+                changed
+            })
+            .reduce(|| false, |a, b| a || b);
+        hollowed.push(if changed {
+            Some(CompressedBitstream::from_image(&image, bits_per_pixel))
+        } else {
+            None
+        });
+        on_layer(index);
+    }
+
+    for (layer, data) in file.layers.iter_mut().zip(hollowed) {
+        if let Some(data) = data {
+            layer.data = data;
+        }
+    }
+    file.header.volume = compute_volume(file);
+    Ok(())
+}
+
+#[test]
+fn test_infill_contains() {
+    let grid = Infill::Grid {
+        spacing: 5.0,
+        thickness: 1.0,
+    };
+    assert!(grid.contains(0.2, 2.5));
+    assert!(grid.contains(2.5, 9.8));
+    assert!(!grid.contains(2.5, 2.5));
+    let honeycomb = Infill::Honeycomb {
+        spacing: 4.0,
+        thickness: 0.5,
+    };
+    assert!(!honeycomb.contains(0.0, 0.0));
+    assert!(honeycomb.contains(1.9, 0.0));
+    assert!(!honeycomb.contains(4.0, 0.0));
+}
+
+#[test]
+fn test_hollow() {
+    use crate::generate::test_job;
+    use image::{GrayImage, Luma};
+
+    // A 7 mm cube in 1 mm layers
+    let block = GrayImage::from_fn(9, 9, |x, y| {
+        Luma([if (1..8).contains(&x) && (1..8).contains(&y) {
+            255
+        } else {
+            0
+        }])
+    });
+    let mut file = test_job(9, 9, &vec![block; 7]);
+    for layer in file.layers.iter_mut() {
+        layer.layer_height = 1.0;
+    }
+    let settings = |wall_thickness| Hollow {
+        wall_thickness,
+        infill: Infill::None,
+    };
+    assert!(hollow(&mut file, &settings(-1.0), |_| {}).is_err());
+    assert!(hollow(&mut file, &settings(0.0), |_| {}).is_err());
+    hollow(&mut file, &settings(1.5), |_| {}).unwrap();
+    // Walls and floors of two layers stay, the 5x5 middle of the three layers between is emptied
+    let lit = |layer: usize, x: u32, y: u32| file.decode_layer(layer).get_pixel(x, y).0[0] != 0;
+    assert!(lit(1, 4, 4) && lit(5, 4, 4));
+    assert!(lit(3, 1, 4) && lit(3, 7, 4));
+    assert!(!lit(3, 2, 4) && !lit(2, 4, 4) && !lit(4, 6, 6));
+    assert!((file.header.volume - (343.0 - 75.0)).abs() < 1e-3);
+}
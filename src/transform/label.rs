@@ -0,0 +1,155 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::font::{render_text, GLYPH_HEIGHT};
+use crate::raster::Bitmap;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LabelMode {
+    Engrave, // Cut the text out of the part
+    Emboss,  // Add the text to the part
+}
+
+/// Single line of text stamped into a range of layers.
+#[derive(Clone, Debug)]
+pub struct Label {
+    pub text: String,
+    pub x: f32,      // Left edge, in mm
+    pub y: f32,      // Top edge, in mm
+    pub height: f32, // Height of the letters, in mm
+    pub first_layer: usize,
+    pub last_layer: usize, // inclusive
+    pub mode: LabelMode,
+    // Mirror the text, so it reads correctly when looking at the bottom of the part.
+    pub mirror: bool,
+}
+
+impl Label {
+    /// Width of the text in mm.
+    pub fn width(&self) -> f32 {
+        render_text(&self.text).width as f32 * self.height / GLYPH_HEIGHT as f32
+    }
+
+    fn stamp(&self, image: &mut GrayImage, text: &Bitmap, pixel_size: f32) -> bool {
+        let module = self.height / GLYPH_HEIGHT as f32 / pixel_size; // Pixels per font pixel
+        let (left, top) = (self.x / pixel_size, self.y / pixel_size);
+        let min_x = left.floor().max(0.0) as u32;
+        let min_y = top.floor().max(0.0) as u32;
+        let max_x = std::cmp::min(
+            (left + text.width as f32 * module).ceil() as u32,
+            image.width(),
+        );
+        let max_y = std::cmp::min(
+            (top + text.height as f32 * module).ceil() as u32,
+            image.height(),
+        );
+        let value = match self.mode {
+            LabelMode::Engrave => 0,
+            LabelMode::Emboss => 255,
+        };
+        let mut changed = false;
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let tx = ((x as f32 + 0.5 - left) / module).floor();
+                let ty = ((y as f32 + 0.5 - top) / module).floor();
+                if tx < 0.0 || ty < 0.0 || tx >= text.width as f32 || ty >= text.height as f32 {
+                    continue;
+                }
+                let tx = if self.mirror {
+                    text.width - 1 - tx as u32
+                } else {
+                    tx as u32
+                };
+                let pixel = image.get_pixel_mut(x, y);
+                if text.get(tx, ty as u32) && pixel.0[0] != value {
+                    pixel.0[0] = value;
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+}
+
+/// Stamps the label into every layer it spans, and updates the volume.
+pub fn apply_label<F>(file: &mut PwsFile, label: &Label, on_layer: F)
+where
+    F: Fn(usize) + Sync,
+{
+    let pixel_size = file.header.pixel_size_mm();
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let text = render_text(&label.text);
+    if file.layers.is_empty() {
+        return;
+    }
+    let first_layer = std::cmp::min(label.first_layer, file.layers.len());
+    let last_layer = std::cmp::min(label.last_layer, file.layers.len() - 1);
+    let file_ref: &PwsFile = file;
+    let stamped: Vec<Option<CompressedBitstream>> = (first_layer..=last_layer)
+        .into_par_iter()
+        .map(|index| {
+            let mut image = file_ref.decode_layer(index);
+            let changed = label.stamp(&mut image, &text, pixel_size);
+            on_layer(index);
+            if changed {
+                Some(CompressedBitstream::from_image(&image, bits_per_pixel))
+            } else {
+                None
+            }
+        })
+        .collect();
+    for (layer, data) in file.layers[first_layer..].iter_mut().zip(stamped) {
+        if let Some(data) = data {
+            layer.data = data;
+        }
+    }
+    file.header.volume = compute_volume(file);
+}
+
+#[test]
+fn test_apply_label() {
+    use crate::generate::test_job;
+    use image::Luma;
+
+    // A "1" with 1 mm font pixels, one pixel in from the top left corner
+    let label = |mode, mirror| Label {
+        text: "1".to_string(),
+        x: 1.0,
+        y: 1.0,
+        height: 7.0,
+        first_layer: 1,
+        last_layer: 5,
+        mode,
+        mirror,
+    };
+    let lit = |file: &PwsFile, layer: usize, x: u32, y: u32| {
+        file.decode_layer(layer).get_pixel(x, y).0[0] != 0
+    };
+
+    let mut file = test_job(8, 9, &vec![GrayImage::new(8, 9); 3]);
+    apply_label(&mut file, &label(LabelMode::Emboss, false), |_| {});
+    let image = file.decode_layer(2);
+    assert_eq!(image.pixels().filter(|pixel| pixel.0[0] != 0).count(), 10);
+    assert!(lit(&file, 2, 3, 1) && !lit(&file, 2, 2, 1));
+    assert!(lit(&file, 2, 2, 2) && !lit(&file, 2, 4, 2));
+    assert!(lit(&file, 2, 2, 7) && lit(&file, 2, 4, 7));
+    assert!(!lit(&file, 0, 3, 1));
+    assert!((file.header.volume - 20.0 * 0.05).abs() < 1e-3);
+
+    // Engraving cuts the same pixels out of a solid layer, mirrored here
+    let solid = GrayImage::from_pixel(8, 9, Luma([255]));
+    let mut file = test_job(8, 9, &vec![solid; 3]);
+    apply_label(&mut file, &label(LabelMode::Engrave, true), |_| {});
+    let image = file.decode_layer(1);
+    assert_eq!(image.pixels().filter(|pixel| pixel.0[0] == 0).count(), 10);
+    assert!(!lit(&file, 1, 3, 1) && !lit(&file, 1, 4, 2) && lit(&file, 1, 2, 2));
+    assert!(lit(&file, 0, 3, 1));
+
+    // Labels above the top layer or on empty jobs change nothing
+    let mut high = label(LabelMode::Engrave, false);
+    high.first_layer = 10;
+    high.last_layer = 12;
+    apply_label(&mut file, &high, |_| {});
+    apply_label(&mut test_job(8, 9, &[]), &high, |_| {});
+}
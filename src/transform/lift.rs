@@ -0,0 +1,129 @@
+use crate::analysis::volume::image_area;
+use crate::formats::pws::data::PwsFile;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Peel forces grow with the area that sticks to the vat film, so every layer gets the lift of the
+ * highest breakpoint its area reaches. Layers below the lowest breakpoint keep their lift, which
+ * is the lift from the header if the job did not use per-layer parameters yet.
+ */
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LiftMetric {
+    Area,         // Lit area of the layer
+    AreaIncrease, // Lit area added compared to the layer below
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct LiftRule {
+    pub min_area: f32,      // in mm^2
+    pub lift_distance: f32, // in mm
+    pub lift_speed: f32,    // in mm/sec
+}
+
+#[derive(Clone, Debug)]
+pub struct LiftRules {
+    pub metric: LiftMetric,
+    pub rules: Vec<LiftRule>,
+}
+
+impl LiftRules {
+    pub fn lookup(&self, area: f32) -> Option<&LiftRule> {
+        self.rules
+            .iter()
+            .filter(|rule| rule.min_area <= area)
+            .max_by(|a, b| a.min_area.total_cmp(&b.min_area))
+    }
+}
+
+/// Sets the lift of every layer from its area, and enables per-layer parameters. Returns the
+/// area measured for every layer in mm^2.
+pub fn apply_lift_rules<F>(file: &mut PwsFile, rules: &LiftRules, on_layer: F) -> Vec<f32>
+where
+    F: Fn(usize) + Sync,
+{
+    file.enable_individual_parameters();
+    let pixel_size = file.header.pixel_size_mm();
+    let file_ref: &PwsFile = file;
+    let areas: Vec<f32> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let area = image_area(&file_ref.decode_layer(index), pixel_size);
+            on_layer(index);
+            area
+        })
+        .collect();
+    for (index, layer) in file.layers.iter_mut().enumerate() {
+        let area = match rules.metric {
+            LiftMetric::Area => areas[index],
+            LiftMetric::AreaIncrease => {
+                let below = if index > 0 { areas[index - 1] } else { 0.0 };
+                (areas[index] - below).max(0.0)
+            }
+        };
+        if let Some(rule) = rules.lookup(area) {
+            layer.lift_distance = rule.lift_distance;
+            layer.lift_speed = rule.lift_speed;
+        }
+    }
+    areas
+}
+
+#[test]
+fn test_lift_rules() {
+    let rule = |min_area, lift_speed| LiftRule {
+        min_area,
+        lift_distance: 6.0,
+        lift_speed,
+    };
+    let rules = LiftRules {
+        metric: LiftMetric::Area,
+        rules: vec![rule(500.0, 1.0), rule(0.0, 3.0), rule(100.0, 2.0)],
+    };
+    assert_eq!(rules.lookup(50.0).unwrap().lift_speed, 3.0);
+    assert_eq!(rules.lookup(100.0).unwrap().lift_speed, 2.0);
+    assert_eq!(rules.lookup(1000.0).unwrap().lift_speed, 1.0);
+    assert!(rules.lookup(-1.0).is_none());
+}
+
+#[test]
+fn test_apply_lift_rules() {
+    use crate::generate::test_job;
+    use image::{GrayImage, Luma};
+
+    let blank = GrayImage::new(8, 8);
+    let lit = GrayImage::from_pixel(8, 8, Luma([255]));
+    let rules = LiftRules {
+        metric: LiftMetric::Area,
+        rules: vec![LiftRule {
+            min_area: 10.0,
+            lift_distance: 8.0,
+            lift_speed: 1.0,
+        }],
+    };
+    let job = || {
+        let mut file = test_job(8, 8, &[lit.clone(), blank.clone()]);
+        for layer in file.layers.iter_mut() {
+            layer.lift_distance = 0.0;
+            layer.lift_speed = 0.0;
+        }
+        file.header.num_bottom_layers = 1.0;
+        file
+    };
+
+    // With per-layer parameters in use, the empty layer keeps its own lift
+    let mut file = job();
+    assert_eq!(apply_lift_rules(&mut file, &rules, |_| ()), vec![64.0, 0.0]);
+    assert_eq!(file.layers[0].lift_speed, 1.0);
+    assert_eq!(file.layers[1].lift_distance, 0.0);
+
+    // Otherwise its stale lift is replaced by the header lift the printer used
+    let mut file = job();
+    file.header.use_individual_parameters = false;
+    apply_lift_rules(&mut file, &rules, |_| ());
+    assert!(file.header.use_individual_parameters);
+    assert_eq!(file.layers[0].lift_speed, 1.0);
+    assert_eq!(file.layers[1].lift_distance, file.header.lift_distance);
+    assert_eq!(file.layers[1].lift_speed, file.header.lift_speed);
+    assert_eq!(file.layers[1].exposure_time, file.header.exposure_time);
+}
@@ -0,0 +1,200 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{CompressedBitstream, PwsFile, PwsLayer};
+use crate::raster::components::BoundingBox;
+use crate::raster::copy_region;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Jobs are packed onto the plate with First-Fit Decreasing Height: footprints are sorted from
+ * tallest to shortest and placed left to right on shelves, opening a new shelf below when a
+ * footprint does not fit on any existing one.
+ */
+
+const TOLERANCE: f32 = 1e-4;
+
+/// Top-left positions for footprints of the given sizes, centred on the plate as a whole.
+pub fn pack_footprints(
+    sizes: &[(u32, u32)],
+    plate: (u32, u32),
+    gap: u32,
+) -> Result<Vec<(u32, u32)>, String> {
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by(|a, b| sizes[*b].1.cmp(&sizes[*a].1));
+    // Every shelf is (top, height, used width)
+    let mut shelves: Vec<(u32, u32, u32)> = Vec::new();
+    let mut positions = vec![(0, 0); sizes.len()];
+    let mut used_height = 0;
+    for index in order {
+        let (width, height) = sizes[index];
+        let shelf = shelves.iter_mut().find(|(_, shelf_height, used_width)| {
+            height <= *shelf_height && used_width + gap + width <= plate.0
+        });
+        match shelf {
+            Some((top, _, used_width)) => {
+                positions[index] = (*used_width + gap, *top);
+                *used_width += gap + width;
+            }
+            None => {
+                let top = if shelves.is_empty() {
+                    0
+                } else {
+                    used_height + gap
+                };
+                if width > plate.0 || top + height > plate.1 {
+                    return Err(format!(
+                        "Job {} ({} x {} pixels) does not fit on the plate",
+                        index, width, height
+                    ));
+                }
+                positions[index] = (0, top);
+                shelves.push((top, height, width));
+                used_height = top + height;
+            }
+        }
+    }
+    let used_width = shelves.iter().map(|shelf| shelf.2).max().unwrap_or(0);
+    let left = (plate.0 - used_width) / 2;
+    let top = (plate.1 - used_height) / 2;
+    Ok(positions
+        .into_iter()
+        .map(|(x, y)| (x + left, y + top))
+        .collect())
+}
+
+/// Differences in exposure settings between the jobs. These are not resolved, merged layers
+/// take their parameters from the first job that has a layer at that height. Per-layer exposures
+/// are compared, so per-layer parameters should be enabled on all jobs first.
+pub fn exposure_conflicts(files: &[PwsFile]) -> Vec<String> {
+    let mut conflicts = Vec::new();
+    let first = match files.first() {
+        Some(first) => &first.header,
+        None => return conflicts,
+    };
+    for (index, file) in files.iter().enumerate().skip(1) {
+        let header = &file.header;
+        let mut compare = |name: &str, a: f32, b: f32| {
+            if (a - b).abs() > TOLERANCE {
+                conflicts.push(format!("Job {} has {} {} instead of {}", index, name, b, a));
+            }
+        };
+        compare("exposure time", first.exposure_time, header.exposure_time);
+        compare(
+            "bottom exposure time",
+            first.bottom_exposure_time,
+            header.bottom_exposure_time,
+        );
+        compare(
+            "bottom layer count",
+            first.num_bottom_layers,
+            header.num_bottom_layers,
+        );
+        compare("off time", first.off_time, header.off_time);
+        let common_layers = std::cmp::min(files[0].layers.len(), file.layers.len());
+        let differing_layers = (0..common_layers)
+            .filter(|layer| {
+                (files[0].layers[*layer].exposure_time - file.layers[*layer].exposure_time).abs()
+                    > TOLERANCE
+            })
+            .count();
+        if differing_layers > 0 {
+            conflicts.push(format!(
+                "Job {} has a different exposure time on {} layers",
+                index, differing_layers
+            ));
+        }
+    }
+    conflicts
+}
+
+/// Checks that the jobs can share layers at all.
+pub fn check_compatible(files: &[PwsFile]) -> Result<(), String> {
+    let first = match files.first() {
+        Some(first) => first,
+        None => return Err("No jobs to merge".to_string()),
+    };
+    for (index, file) in files.iter().enumerate().skip(1) {
+        if (file.header.pixel_size - first.header.pixel_size).abs() > TOLERANCE {
+            return Err(format!(
+                "Job {} has pixel size {} um instead of {} um, resample it first",
+                index, file.header.pixel_size, first.header.pixel_size
+            ));
+        }
+        let common_layers = std::cmp::min(first.layers.len(), file.layers.len());
+        for layer in 0..common_layers {
+            if (file.layer_thickness(layer) - first.layer_thickness(layer)).abs() > TOLERANCE {
+                return Err(format!(
+                    "Job {} has layer height {} mm instead of {} mm at layer {}",
+                    index,
+                    file.layer_thickness(layer),
+                    first.layer_thickness(layer),
+                    layer
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Combines the jobs into one, copying the occupied `bounds` of every job to its position on the
+/// plate of the first job. Shorter jobs are padded with empty layers. Layer parameters are
+/// copied, so per-layer parameters should be enabled on all jobs first.
+pub fn merge_jobs<F>(
+    files: &[PwsFile],
+    bounds: &[BoundingBox],
+    positions: &[(u32, u32)],
+    on_layer: F,
+) -> PwsFile
+where
+    F: Fn(usize) + Sync,
+{
+    let first = &files[0];
+    let mut header = first.header.clone();
+    header.bits_per_pixel = files
+        .iter()
+        .map(|file| file.header.bits_per_pixel)
+        .max()
+        .unwrap();
+    let (width, height) = (header.width, header.height);
+    let bits_per_pixel = header.bits_per_pixel as usize;
+    let num_layers = files.iter().map(|file| file.layers.len()).max().unwrap();
+    let layers: Vec<PwsLayer> = (0..num_layers)
+        .into_par_iter()
+        .map(|index| {
+            let mut output = GrayImage::new(width, height);
+            let mut parameters = None;
+            for ((file, bounds), (x, y)) in files.iter().zip(bounds).zip(positions) {
+                if index >= file.layers.len() {
+                    continue;
+                }
+                let image = file.decode_layer(index);
+                copy_region(&mut output, &image, bounds, *x, *y);
+                parameters = parameters.or_else(|| Some(&file.layers[index]));
+            }
+            let parameters = parameters.unwrap();
+            on_layer(index);
+            PwsLayer {
+                lift_distance: parameters.lift_distance,
+                lift_speed: parameters.lift_speed,
+                exposure_time: parameters.exposure_time,
+                layer_height: parameters.layer_height,
+                data: CompressedBitstream::from_image(&output, bits_per_pixel),
+            }
+        })
+        .collect();
+    let mut merged = PwsFile {
+        header,
+        preview: first.preview.clone(),
+        layers,
+    };
+    merged.header.volume = compute_volume(&merged);
+    merged
+}
+
+#[test]
+fn test_pack_footprints() {
+    let positions = pack_footprints(&[(4, 2), (3, 5), (3, 2)], (10, 10), 1).unwrap();
+    // Tallest first on the top shelf, the last one no longer fits next to it
+    assert_eq!(positions, vec![(5, 1), (1, 1), (1, 7)]);
+    assert!(pack_footprints(&[(6, 6), (6, 6)], (10, 10), 1).is_err());
+}
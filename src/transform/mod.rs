@@ -0,0 +1,14 @@
+pub mod adaptive;
+pub mod antialias;
+pub mod array;
+pub mod drain;
+pub mod elephant_foot;
+pub mod geometry;
+pub mod hollow;
+pub mod label;
+pub mod lift;
+pub mod merge;
+pub mod resample;
+pub mod resume;
+pub mod uniformity;
+pub mod zresample;
@@ -0,0 +1,44 @@
+use crate::formats::pws::data::{CompressedBitstream, PwsFile};
+use crate::raster::resample::resample;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// Resamples every layer onto a plate of `width` x `height` pixels of `pixel_size` um, keeping
+/// the physical size of the part and centring it. Returns the layers that lost lit pixels off the
+/// edge of the new plate, with the number of pixels lost.
+pub fn resample_job<F>(
+    file: &mut PwsFile,
+    width: u32,
+    height: u32,
+    pixel_size: f32,
+    on_layer: F,
+) -> Vec<(usize, usize)>
+where
+    F: Fn(usize) + Sync,
+{
+    let source_pixel_size = file.header.pixel_size;
+    let bits_per_pixel = file.header.bits_per_pixel as usize;
+    let file_ref: &PwsFile = file;
+    let resampled: Vec<(CompressedBitstream, usize)> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let image = file_ref.decode_layer(index);
+            let (image, clipped) = resample(&image, source_pixel_size, width, height, pixel_size);
+            on_layer(index);
+            (
+                CompressedBitstream::from_image(&image, bits_per_pixel),
+                clipped,
+            )
+        })
+        .collect();
+    file.header.width = width;
+    file.header.height = height;
+    file.header.pixel_size = pixel_size;
+    let mut clipped_layers = Vec::new();
+    for (index, (layer, (data, clipped))) in file.layers.iter_mut().zip(resampled).enumerate() {
+        layer.data = data;
+        if clipped > 0 {
+            clipped_layers.push((index, clipped));
+        }
+    }
+    clipped_layers
+}
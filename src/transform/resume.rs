@@ -0,0 +1,73 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::PwsFile;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Resume {
+    pub start_layer: usize,
+    // Number of new layers exposed as bottom layers, to bond them to the partial part.
+    pub bottom_layers: usize,
+    pub bottom_exposure_time: Option<f32>, // in sec, defaults to that of the original job
+}
+
+/// Drops all layers before the start layer. The first new layers get the bottom exposure, and
+/// layers that used to be bottom layers but are no longer get the normal exposure.
+pub fn resume_from_layer(file: &mut PwsFile, settings: &Resume) -> Result<(), String> {
+    if settings.start_layer >= file.layers.len() {
+        return Err(format!(
+            "Start layer {} is beyond the last layer {}",
+            settings.start_layer,
+            file.layers.len().saturating_sub(1)
+        ));
+    }
+    let header = &mut file.header;
+    let old_bottom_layers = header.num_bottom_layers.round() as usize;
+    let bottom_exposure_time = settings
+        .bottom_exposure_time
+        .unwrap_or(header.bottom_exposure_time);
+    file.layers.drain(..settings.start_layer);
+    for (index, layer) in file.layers.iter_mut().enumerate() {
+        if index < settings.bottom_layers {
+            layer.exposure_time = bottom_exposure_time;
+        } else if index + settings.start_layer < old_bottom_layers {
+            layer.exposure_time = header.exposure_time;
+        }
+    }
+    header.bottom_exposure_time = bottom_exposure_time;
+    header.num_bottom_layers = settings.bottom_layers as f32;
+    file.header.volume = compute_volume(file);
+    Ok(())
+}
+
+#[test]
+fn test_resume_from_layer() {
+    use crate::generate::test_job;
+    use image::GrayImage;
+
+    let mut file = test_job(2, 2, &vec![GrayImage::new(2, 2); 6]);
+    file.header.num_bottom_layers = 3.0;
+    for layer in file.layers.iter_mut().take(3) {
+        layer.exposure_time = 60.0;
+    }
+    let resume = Resume {
+        start_layer: 1,
+        bottom_layers: 1,
+        bottom_exposure_time: Some(30.0),
+    };
+    resume_from_layer(&mut file, &resume).unwrap();
+    let exposures: Vec<f32> = file
+        .layers
+        .iter()
+        .map(|layer| layer.exposure_time)
+        .collect();
+    // The old bottom layer 2 is no longer a bottom layer
+    assert_eq!(exposures, vec![30.0, 8.0, 8.0, 8.0, 8.0]);
+    assert_eq!(file.header.num_bottom_layers, 1.0);
+    assert!(resume_from_layer(
+        &mut file,
+        &Resume {
+            start_layer: 5,
+            ..resume
+        }
+    )
+    .is_err());
+}
@@ -0,0 +1,137 @@
+use crate::formats::pws::data::{supported_bits_per_pixel, CompressedBitstream, PwsFile};
+use crate::raster::dither::{dither, Dither};
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * The map is stretched over the whole plate, with its samples at the centres of equally sized
+ * cells, and bilinearly interpolated in between. Multiplying a layer by it dims the parts of the
+ * plate where the backlight is brightest, which needs enough anti-aliasing levels to show.
+ * Corrections of a few percent are smaller than the step between two levels, so the dimmed
+ * layers are dithered to keep their average intensity.
+ */
+
+/// Factors between 0 and 1 to multiply the layers by, on a coarse grid over the plate.
+#[derive(Clone, PartialEq, Debug)]
+pub struct UniformityMap {
+    pub width: u32,
+    pub height: u32,
+    pub factors: Vec<f32>,
+}
+
+impl UniformityMap {
+    /// Map from a greyscale image, white leaves the layers as they are.
+    pub fn from_image(image: &GrayImage) -> UniformityMap {
+        UniformityMap {
+            width: image.width(),
+            height: image.height(),
+            factors: image.pixels().map(|p| p.0[0] as f32 / 255.0).collect(),
+        }
+    }
+
+    /// Map from rows of light intensity readings, dimming every point down to the weakest.
+    pub fn from_measurements(rows: &[Vec<f32>]) -> Result<UniformityMap, String> {
+        let width = rows.first().map_or(0, |row| row.len());
+        if width == 0 || rows.iter().any(|row| row.len() != width) {
+            return Err("Every row needs the same, non-zero number of readings".to_string());
+        }
+        let readings: Vec<f32> = rows.iter().flatten().cloned().collect();
+        if readings.iter().any(|reading| *reading <= 0.0) {
+            return Err("Readings should be positive".to_string());
+        }
+        let weakest = readings.iter().cloned().fold(f32::INFINITY, f32::min);
+        Ok(UniformityMap {
+            width: width as u32,
+            height: rows.len() as u32,
+            factors: readings.iter().map(|reading| weakest / reading).collect(),
+        })
+    }
+
+    fn get(&self, x: u32, y: u32) -> f32 {
+        self.factors[(y * self.width + x) as usize]
+    }
+
+    /// Factor at a point given as a fraction of the plate width and height.
+    pub fn factor(&self, u: f32, v: f32) -> f32 {
+        let x = (u * self.width as f32 - 0.5).clamp(0.0, (self.width - 1) as f32);
+        let y = (v * self.height as f32 - 0.5).clamp(0.0, (self.height - 1) as f32);
+        let (x0, y0) = (x as u32, y as u32);
+        let (x1, y1) = (
+            std::cmp::min(x0 + 1, self.width - 1),
+            std::cmp::min(y0 + 1, self.height - 1),
+        );
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = self.get(x0, y0) * (1.0 - fx) + self.get(x1, y0) * fx;
+        let bottom = self.get(x0, y1) * (1.0 - fx) + self.get(x1, y1) * fx;
+        top * (1.0 - fy) + bottom * fy
+    }
+}
+
+/// Multiplies every layer by the map. The file is re-encoded with at least `bits_per_pixel`
+/// anti-aliasing levels, as dimming needs grey levels, see `supported_bits_per_pixel`. The
+/// volume is left as it is, as the geometry does not change.
+pub fn compensate_uniformity<F>(
+    file: &mut PwsFile,
+    map: &UniformityMap,
+    bits_per_pixel: u32,
+    on_layer: F,
+) where
+    F: Fn(usize) + Sync,
+{
+    let (width, height) = (file.header.width, file.header.height);
+    let factors: Vec<f32> = (0..width * height)
+        .map(|index| {
+            let (x, y) = (index % width, index / width);
+            map.factor(
+                (x as f32 + 0.5) / width as f32,
+                (y as f32 + 0.5) / height as f32,
+            )
+        })
+        .collect();
+    let bits_per_pixel =
+        supported_bits_per_pixel(std::cmp::max(bits_per_pixel, file.header.bits_per_pixel));
+    let file_ref: &PwsFile = file;
+    let compensated: Vec<CompressedBitstream> = (0..file.layers.len())
+        .into_par_iter()
+        .map(|index| {
+            let mut image = file_ref.decode_layer(index);
+            for (pixel, factor) in image.pixels_mut().zip(factors.iter()) {
+                pixel.0[0] = (pixel.0[0] as f32 * factor).round() as u8;
+            }
+            let image = dither(&image, bits_per_pixel as usize, Dither::FloydSteinberg);
+            on_layer(index);
+            CompressedBitstream::from_image(&image, bits_per_pixel as usize)
+        })
+        .collect();
+    for (layer, data) in file.layers.iter_mut().zip(compensated) {
+        layer.data = data;
+    }
+    file.header.bits_per_pixel = bits_per_pixel;
+}
+
+#[test]
+fn test_uniformity_map() {
+    let map = UniformityMap::from_measurements(&[vec![2.0, 4.0], vec![2.0, 2.0]]).unwrap();
+    assert_eq!(map.factors, vec![1.0, 0.5, 1.0, 1.0]);
+    // Sample centres, the middle of the plate and beyond the outer samples
+    assert_eq!(map.factor(0.75, 0.25), 0.5);
+    assert_eq!(map.factor(0.5, 0.25), 0.75);
+    assert_eq!(map.factor(1.0, 0.0), 0.5);
+    assert!(UniformityMap::from_measurements(&[vec![1.0], vec![]]).is_err());
+}
+
+#[test]
+fn test_compensate_uniformity() {
+    use crate::generate::test_job;
+    use image::Luma;
+
+    let image = GrayImage::from_pixel(16, 16, Luma([255]));
+    let mut file = test_job(16, 16, &[image]);
+    // A 6% correction is less than a single step of 4 levels, and 3 levels are not readable
+    let map = UniformityMap::from_image(&GrayImage::from_pixel(1, 1, Luma([240])));
+    compensate_uniformity(&mut file, &map, 3, |_| ());
+    assert_eq!(file.header.bits_per_pixel, 4);
+    let compensated = file.decode_layer(0);
+    let mean = compensated.pixels().map(|p| p.0[0] as f32).sum::<f32>() / 256.0;
+    assert!((mean - 240.0).abs() < 2.0, "mean is {}", mean);
+}
@@ -0,0 +1,334 @@
+use crate::analysis::volume::compute_volume;
+use crate::formats::pws::data::{supported_bits_per_pixel, CompressedBitstream, PwsFile, PwsLayer};
+use crate::raster::distance::distance_to;
+use crate::raster::Bitmap;
+use image::GrayImage;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/*
+ * Exposure is rescaled with the Jacobs working curve, cure depth = Dp * ln(E / Ec). Curing a
+ * layer of thickness h' instead of h needs E' = E * exp((h' - h) / Dp), where Dp is the
+ * penetration depth of the resin.
+ *
+ * With Z anti-aliasing, merged layers are averaged weighted by how much of the new layer they
+ * cover instead of combined. Split layers are only partially filled when interpolating, the
+ * fill is then found by averaging the morphed cross-section at several heights.
+ */
+
+const Z_SAMPLES: usize = 4; // Cross-sections averaged per split layer with Z anti-aliasing
+
+#[derive(Clone, Copy, Debug)]
+pub struct ZResample {
+    pub layer_height: f32,      // New layer height, in mm
+    pub penetration_depth: f32, // Resin penetration depth Dp, in mm
+    // When splitting layers, morph between neighbouring layers instead of repeating them.
+    pub interpolate: bool,
+    // Encode how much of every pixel is filled over the height of the new layer as grey, with at
+    // least this many anti-aliasing levels, see `supported_bits_per_pixel`.
+    pub antialias_levels: Option<u32>,
+}
+
+/// Factor to scale the exposure by when a layer changes thickness.
+pub fn exposure_factor(old_height: f32, new_height: f32, penetration_depth: f32) -> f32 {
+    ((new_height - old_height) / penetration_depth).exp()
+}
+
+impl ZResample {
+    pub fn exposure_factor(&self, old_height: f32) -> f32 {
+        exposure_factor(old_height, self.layer_height, self.penetration_depth)
+    }
+}
+
+/// Signed distance to the edge of the lit area, negative inside.
+fn signed_distance(image: &GrayImage) -> Vec<f32> {
+    let bitmap = Bitmap::from_image(image);
+    let outside = distance_to(&bitmap, true);
+    let inside = distance_to(&bitmap, false);
+    outside
+        .into_iter()
+        .zip(inside)
+        .map(|(outside, inside)| outside - inside)
+        .collect()
+}
+
+/// Morphs between two cross-sections, `t` = 0 gives `a` and `t` = 1 gives `b`.
+pub fn interpolate_layers(a: &GrayImage, b: &GrayImage, t: f32) -> GrayImage {
+    let a_distance = signed_distance(a);
+    let b_distance = signed_distance(b);
+    let pixels = a_distance
+        .into_iter()
+        .zip(b_distance)
+        .map(|(a, b)| {
+            let distance = a * (1.0 - t) + b * t;
+            // Half a pixel of anti-aliasing across the edge
+            ((0.5 - distance).clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+        .collect();
+    GrayImage::from_raw(a.width(), a.height(), pixels).unwrap()
+}
+
+/// Union of several layers, taking the brightest value of every pixel.
+pub fn merge_layers(images: Vec<GrayImage>) -> GrayImage {
+    let mut images = images.into_iter();
+    let mut merged = images.next().unwrap();
+    for image in images {
+        for (target, source) in merged.pixels_mut().zip(image.pixels()) {
+            target.0[0] = std::cmp::max(target.0[0], source.0[0]);
+        }
+    }
+    merged
+}
+
+/// Average of several layers, with weights adding up to one.
+fn average_layers(images: &[GrayImage], weights: &[f32]) -> GrayImage {
+    let mut sums = vec![0.0; images[0].as_ref().len()];
+    for (image, weight) in images.iter().zip(weights) {
+        for (sum, pixel) in sums.iter_mut().zip(image.pixels()) {
+            *sum += pixel.0[0] as f32 * weight;
+        }
+    }
+    let pixels = sums
+        .into_iter()
+        .map(|sum| sum.round().min(255.0) as u8)
+        .collect();
+    GrayImage::from_raw(images[0].width(), images[0].height(), pixels).unwrap()
+}
+
+/// Cross-section at height `z`, morphed between the layer containing it and the neighbour on
+/// the side of `z`. `bottoms` holds the bottom of every layer and the top of the last.
+fn interpolated_layer(file: &PwsFile, bottoms: &[f32], z: f32) -> GrayImage {
+    let num_layers = file.layers.len();
+    let containing = file.layer_at_height(z);
+    let source_centre = (bottoms[containing] + bottoms[containing + 1]) / 2.0;
+    let neighbour = if z < source_centre {
+        containing.checked_sub(1)
+    } else if containing + 1 < num_layers {
+        Some(containing + 1)
+    } else {
+        None
+    };
+    let image = file.decode_layer(containing);
+    match neighbour {
+        Some(neighbour) => {
+            let neighbour_centre = (bottoms[neighbour] + bottoms[neighbour + 1]) / 2.0;
+            let t = (z - source_centre) / (neighbour_centre - source_centre);
+            let neighbour_image = file.decode_layer(neighbour);
+            interpolate_layers(&image, &neighbour_image, t)
+        }
+        None => image,
+    }
+}
+
+/// Replaces all layers by layers of the new height covering the same total height.
+pub fn resample_layer_height<F>(
+    file: &mut PwsFile,
+    settings: &ZResample,
+    on_layer: F,
+) -> Result<(), String>
+where
+    F: Fn(usize) + Sync,
+{
+    if !(settings.layer_height > 0.0 && settings.layer_height.is_finite()) {
+        return Err(format!(
+            "Layer height of {} mm should be positive",
+            settings.layer_height
+        ));
+    }
+    if !(settings.penetration_depth > 0.0 && settings.penetration_depth.is_finite()) {
+        return Err(format!(
+            "Penetration depth of {} mm should be positive",
+            settings.penetration_depth
+        ));
+    }
+    // Exposures are averaged per layer, which are only meaningful when the printer uses them
+    file.enable_individual_parameters();
+    let num_layers = file.layers.len();
+    if num_layers == 0 {
+        return Ok(());
+    }
+    let mut bottoms = Vec::with_capacity(num_layers + 1);
+    let mut current = 0.0;
+    for index in 0..num_layers {
+        bottoms.push(current);
+        current += file.layer_thickness(index);
+    }
+    bottoms.push(current);
+    let total_height = current;
+    let new_count = (total_height / settings.layer_height).round().max(1.0) as usize;
+    let bottom_height =
+        bottoms[std::cmp::min(file.header.num_bottom_layers.round() as usize, num_layers)];
+    let bits_per_pixel = supported_bits_per_pixel(std::cmp::max(
+        file.header.bits_per_pixel,
+        settings.antialias_levels.unwrap_or(0),
+    ));
+
+    let file_ref: &PwsFile = file;
+    let bottoms_ref = &bottoms;
+    let layers: Vec<PwsLayer> = (0..new_count)
+        .into_par_iter()
+        .map(|new_index| {
+            let start = new_index as f32 * settings.layer_height;
+            let end = start + settings.layer_height;
+            let centre = (start + end) / 2.0;
+            let overlapping: Vec<usize> = (0..num_layers)
+                .filter(|index| {
+                    bottoms_ref[*index] < end - 1e-6 && bottoms_ref[*index + 1] > start + 1e-6
+                })
+                .collect();
+            let containing = file_ref.layer_at_height(centre);
+            let overlapping = if overlapping.is_empty() {
+                vec![containing]
+            } else {
+                overlapping
+            };
+
+            // How much of the new layer each source layer covers
+            let weights: Vec<f32> = overlapping
+                .iter()
+                .map(|index| {
+                    let overlap = end.min(bottoms_ref[*index + 1]) - start.max(bottoms_ref[*index]);
+                    overlap.max(1e-6)
+                })
+                .collect();
+            let total_weight: f32 = weights.iter().sum();
+
+            let image = if overlapping.len() > 1 {
+                let images: Vec<GrayImage> = overlapping
+                    .iter()
+                    .map(|index| file_ref.decode_layer(*index))
+                    .collect();
+                if settings.antialias_levels.is_some() {
+                    let fractions: Vec<f32> =
+                        weights.iter().map(|weight| weight / total_weight).collect();
+                    average_layers(&images, &fractions)
+                } else {
+                    merge_layers(images)
+                }
+            } else if settings.interpolate && settings.antialias_levels.is_some() {
+                let images: Vec<GrayImage> = (0..Z_SAMPLES)
+                    .map(|sample| {
+                        let z = start + (sample as f32 + 0.5) / Z_SAMPLES as f32 * (end - start);
+                        interpolated_layer(file_ref, bottoms_ref, z)
+                    })
+                    .collect();
+                average_layers(&images, &[1.0 / Z_SAMPLES as f32; Z_SAMPLES])
+            } else if settings.interpolate {
+                interpolated_layer(file_ref, bottoms_ref, centre)
+            } else {
+                file_ref.decode_layer(containing)
+            };
+
+            // Exposure is weighted the same way
+            let exposure_time: f32 = overlapping
+                .iter()
+                .zip(weights.iter())
+                .map(|(index, weight)| {
+                    weight
+                        * file_ref.layers[*index].exposure_time
+                        * settings.exposure_factor(file_ref.layer_thickness(*index))
+                })
+                .sum();
+            let first = &file_ref.layers[overlapping[0]];
+            on_layer(new_index);
+            PwsLayer {
+                lift_distance: first.lift_distance,
+                lift_speed: first.lift_speed,
+                exposure_time: exposure_time / total_weight,
+                layer_height: settings.layer_height,
+                data: CompressedBitstream::from_image(&image, bits_per_pixel as usize),
+            }
+        })
+        .collect();
+
+    let header = &mut file.header;
+    let factor = settings.exposure_factor(header.layer_height);
+    header.exposure_time *= factor;
+    header.bottom_exposure_time *= factor;
+    header.num_bottom_layers = (bottom_height / settings.layer_height).round();
+    header.layer_height = settings.layer_height;
+    header.bits_per_pixel = bits_per_pixel;
+    file.layers = layers;
+    file.header.volume = compute_volume(file);
+    Ok(())
+}
+
+#[test]
+fn test_resample_layer_height() {
+    use crate::generate::test_job;
+    use image::Luma;
+
+    let left = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 0 { 255 } else { 0 }]));
+    let right = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 1 { 255 } else { 0 }]));
+    let settings = |layer_height| ZResample {
+        layer_height,
+        penetration_depth: 0.15,
+        interpolate: false,
+        antialias_levels: None,
+    };
+    let close = |a: f32, b: f32| (a - b).abs() < 1e-4;
+    let lit = |file: &PwsFile, layer: usize| -> Vec<u8> { file.decode_layer(layer).into_raw() };
+
+    let mut file = test_job(2, 1, &[left.clone(), right.clone()]);
+    assert!(resample_layer_height(&mut file, &settings(0.0), |_| {}).is_err());
+    assert!(resample_layer_height(&mut file, &settings(-0.1), |_| {}).is_err());
+
+    // Two 0.05 mm layers merge into their union, exposed longer for the thicker layer
+    resample_layer_height(&mut file, &settings(0.1), |_| {}).unwrap();
+    assert_eq!(file.layers.len(), 1);
+    assert_eq!(lit(&file, 0), vec![255, 255]);
+    assert!(close(file.layers[0].layer_height, 0.1));
+    assert!(close(
+        file.layers[0].exposure_time,
+        8.0 * (1.0f32 / 3.0).exp()
+    ));
+    assert!(close(file.header.exposure_time, 8.0 * (1.0f32 / 3.0).exp()));
+
+    // One 0.1 mm layer splits into two copies, exposed shorter
+    let mut file = test_job(2, 1, std::slice::from_ref(&left));
+    file.layers[0].layer_height = 0.1;
+    resample_layer_height(&mut file, &settings(0.05), |_| {}).unwrap();
+    assert_eq!(file.layers.len(), 2);
+    assert_eq!(lit(&file, 0), vec![255, 0]);
+    assert_eq!(lit(&file, 1), vec![255, 0]);
+    assert!(close(
+        file.layers[1].exposure_time,
+        8.0 * (-1.0f32 / 3.0).exp()
+    ));
+
+    // Unused per-layer exposures are replaced by the header exposures before averaging
+    let mut file = test_job(2, 1, &[left, right]);
+    file.header.use_individual_parameters = false;
+    file.header.num_bottom_layers = 1.0;
+    for layer in file.layers.iter_mut() {
+        layer.exposure_time = 0.0;
+    }
+    resample_layer_height(&mut file, &settings(0.1), |_| {}).unwrap();
+    assert!(file.header.use_individual_parameters);
+    let factor = exposure_factor(0.05, 0.1, 0.15);
+    assert!(close(
+        file.layers[0].exposure_time,
+        (60.0 + 8.0) / 2.0 * factor
+    ));
+    assert!(close(file.header.bottom_exposure_time, 60.0 * factor));
+}
+
+#[test]
+fn test_z_antialias() {
+    use crate::generate::test_job;
+    use image::Luma;
+
+    let left = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 0 { 255 } else { 0 }]));
+    let right = GrayImage::from_fn(2, 1, |x, _| Luma([if x == 1 { 255 } else { 0 }]));
+    let mut file = test_job(2, 1, &[left, right]);
+    // 3 levels can not be read by printers, so 4 are used
+    let settings = ZResample {
+        layer_height: 0.1,
+        penetration_depth: 0.15,
+        interpolate: false,
+        antialias_levels: Some(3),
+    };
+    resample_layer_height(&mut file, &settings, |_| {}).unwrap();
+    assert_eq!(file.header.bits_per_pixel, 4);
+    // Both pixels are filled over half the height of the merged layer
+    assert_eq!(file.decode_layer(0).into_raw(), vec![127, 127]);
+}